@@ -11,7 +11,7 @@ Set-Location $STAGE
 $ZIP = "$SRC_DIR\$($Env:CRATE_NAME)-$($Env:APPVEYOR_REPO_TAG_NAME)-$($Env:TARGET).zip"
 
 # TODO Update this to package the right artifacts
-Copy-Item "$SRC_DIR\target\$($Env:TARGET)\release\examples\github.exe" '.\'
+Copy-Item "$SRC_DIR\target\$($Env:TARGET)\release\examples\cloud.exe" '.\'
 
 7z a "$ZIP" *
 
@@ -18,10 +18,10 @@ main() {
     test -f Cargo.lock || cargo generate-lockfile
 
     # TODO Update this to build the artifacts that matter to you
-    cross build --example github --target $TARGET --release --all-features
+    cross build --example cloud --target $TARGET --release --all-features
 
     # TODO Update this to package the right artifacts
-    cp target/$TARGET/release/examples/github $stage/
+    cp target/$TARGET/release/examples/cloud $stage/
 
     cd $stage
     tar czf $src/$CRATE_NAME-$TRAVIS_TAG-$TARGET.tar.gz *
@@ -1,30 +1,30 @@
 /*!
-Example updating an executable to the latest version released via GitHub
+Example updating an executable to the latest version released via the cloud api
 */
 
 // For the `cargo_crate_version!` macro
 #[macro_use]
-extern crate self_update;
+extern crate update;
 
 fn run() -> Result<(), Box<dyn ::std::error::Error>> {
-    let releases = self_update::backends::github::ReleaseList::configure()
-        .repo_owner("jaemk")
-        .repo_name("self_update")
+    let api_root = update::backends::cloud::DEFAULT_API_ROOT;
+    let releases = update::backends::cloud::ReleaseList::configure()
+        .custom_url(api_root)
         .build()?
         .fetch()?;
     println!("found releases:");
     println!("{:#?}\n", releases);
 
-    let status = self_update::backends::github::Update::configure()
-        .repo_owner("jaemk")
-        .repo_name("self_update")
-        .bin_name("github")
+    let status = update::backends::cloud::Update::configure()
+        .name("cloud")
+        .custom_url(api_root)
+        .bin_name("cloud")
         .show_download_progress(true)
         //.target_version_tag("v9.9.10")
         //.show_output(false)
         //.no_confirm(true)
         //
-        // For private repos, you will need to provide a GitHub auth token
+        // For private deployments, you will need to provide an auth token
         // **Make sure not to bake the token into your app**; it is recommended
         // you obtain it via another mechanism, such as environment variables
         // or prompting the user for input
@@ -0,0 +1,259 @@
+/*! Async updates
+
+Implementation of `ReleaseUpdate::update_async`, enabled by the `async` feature.
+Release lookups, the checksum manifest and the asset download go through `reqwest`'s
+async client, the progress bar is updated as the download's chunks are polled.
+Installing the downloaded release still blocks the task it's awaited on.
+*/
+use std::path::Path;
+
+use crate::{
+    errors::*,
+    update::{self, Release, ReleaseAsset, ReleaseUpdate, StagedAsset, UpdateStatus},
+    Status,
+};
+
+// See `ReleaseUpdate::update_async`
+pub(crate) async fn update<U: ReleaseUpdate + ?Sized>(updater: &U) -> Result<Status> {
+    let current_version = updater.current_version();
+    let status = update_extended(updater)
+        .await
+        .map(|s| s.into_status(current_version.clone()));
+    if !updater.dry_run() {
+        update::run_outcome_cmd(updater, &status);
+        notify_webhook(updater, &current_version, &status).await;
+    }
+    status
+}
+
+// Async counterpart of the `on_complete_webhook` notification, through the `async_client`
+async fn notify_webhook<U: ReleaseUpdate + ?Sized>(
+    updater: &U,
+    old_version: &str,
+    status: &Result<Status>,
+) {
+    let (url, payload) = match update::webhook_payload(updater, old_version, status) {
+        Some(notification) => notification,
+        None => return,
+    };
+    let client = match async_client(updater) {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("Failed to notify webhook:{:?}, {}", url, e);
+            return;
+        }
+    };
+    let r = match client.post(&url).json(&payload).send().await {
+        Ok(resp) => resp.error_for_status().map(|resp| resp.status()),
+        Err(e) => Err(e),
+    };
+    update::log_webhook(&url, r);
+}
+
+// The backend's `async_client`, or a new client built from its `client_options`
+fn async_client<U: ReleaseUpdate + ?Sized>(updater: &U) -> Result<reqwest::Client> {
+    match updater.async_client() {
+        Some(client) => Ok(client),
+        None => update::client_options(updater).build_async_client(),
+    }
+}
+
+async fn update_extended<U: ReleaseUpdate + ?Sized>(updater: &U) -> Result<UpdateStatus> {
+    let marker_path = update::begin_success_marker(updater)?;
+    let status = check_and_install(updater).await;
+    update::finish_success_marker(marker_path, &status)?;
+    status
+}
+
+// Resolve the target release and install it when newer than the current version
+async fn check_and_install<U: ReleaseUpdate + ?Sized>(updater: &U) -> Result<UpdateStatus> {
+    let current_version = updater.current_version();
+    info!("Current version:{}", &current_version);
+
+    let release = match updater.target_version() {
+        None => updater.get_latest_release_async().await?,
+        Some(ref ver) => updater.get_release_version_async(ver).await?,
+    };
+    if updater.target_version().is_none() && !update::is_newer(updater, &release) {
+        if updater.ignore_ver_compare() {
+            info!(
+                "Ignore version Compare,target version:{} ",
+                &release.version
+            );
+        } else {
+            info!("Current version:{} is the latest version", &current_version);
+            return Ok(UpdateStatus::UpToDate(current_version));
+        }
+    }
+    install_release(updater, release).await
+}
+
+async fn install_release<U: ReleaseUpdate + ?Sized>(
+    updater: &U,
+    release: Release,
+) -> Result<UpdateStatus> {
+    let current_version = updater.current_version();
+    let target_asset = update::select_asset(updater, &release)?;
+    let extras = update::extra_release_assets(updater, &release)?;
+    if updater.dry_run() {
+        return update::plan_update(updater, &release, target_asset);
+    }
+    if !updater.no_confirm() {
+        bail!(
+            Error::Config,
+            "`update_async` can't prompt for confirmation, `no_confirm` must be set"
+        )
+    }
+    if updater.sandbox_download() {
+        bail!(
+            Error::Config,
+            "`sandbox_download` isn't supported by `update_async`"
+        )
+    }
+    let client = async_client(updater)?;
+
+    let tmp_dir = tempfile::Builder::new()
+        .prefix(&format!("{}_download", updater.bin_name()))
+        .tempdir_in(update::staging_parent(updater)?)?;
+    let staged = download_asset(updater, &client, &release, &target_asset, tmp_dir.path()).await?;
+    let mut stats = staged.stats;
+    let extra_names = extras.iter().map(|a| a.name.clone()).collect::<Vec<_>>();
+    for extra in extras {
+        let extra = download_asset(updater, &client, &release, &extra, tmp_dir.path()).await?;
+        stats = update::add_stats(stats, extra.stats);
+    }
+    wait_can_apply(updater, &release, &staged, &extra_names).await?;
+    let hooks = update::install_with_hooks(updater, &staged, &release.version)?;
+
+    Ok(UpdateStatus::Updated(
+        current_version,
+        release,
+        stats,
+        hooks,
+    ))
+}
+
+// Async counterpart of `update::wait_can_apply`, sleeping between polls without blocking
+// the runtime
+async fn wait_can_apply<U: ReleaseUpdate + ?Sized>(
+    updater: &U,
+    release: &Release,
+    staged: &StagedAsset,
+    extras: &[String],
+) -> Result<()> {
+    let (can_apply, timeout) = match updater.can_apply() {
+        Some(can_apply) => can_apply,
+        None => return Ok(()),
+    };
+    let start = std::time::Instant::now();
+    loop {
+        if can_apply.check() {
+            return Ok(());
+        }
+        let elapsed = start.elapsed();
+        if elapsed >= timeout {
+            break;
+        }
+        let poll = std::cmp::min(update::CAN_APPLY_POLL_INTERVAL, timeout - elapsed);
+        tokio::time::sleep(poll).await;
+    }
+    update::can_apply_timed_out(updater, release, staged, extras, timeout)
+}
+
+// Download `asset` into `dir`, checking its size and checksums
+async fn download_asset<U: ReleaseUpdate + ?Sized>(
+    updater: &U,
+    client: &reqwest::Client,
+    release: &Release,
+    asset: &ReleaseAsset,
+    dir: &Path,
+) -> Result<StagedAsset> {
+    let archive = dir.join(&asset.name);
+    info!(
+        "Download version:{} ,url :{:?}",
+        &release.version, &asset.download_url
+    );
+
+    let manifest_hash = match updater.checksum_manifest() {
+        Some(manifest) => {
+            Some(fetch_manifest_checksum(updater, client, release, &manifest, &asset.name).await?)
+        }
+        None => None,
+    };
+    let signature = match updater.verifying_key() {
+        Some(_) => Some(fetch_signature(updater, client, asset).await?),
+        None => None,
+    };
+    let stats = match updater.serve_asset(asset) {
+        Some(content) => {
+            std::fs::write(&archive, content?)?;
+            None
+        }
+        None => {
+            let archive_file = std::fs::File::create(&archive)?;
+            let download = update::asset_download(updater, asset)?;
+            download.download_to_async(client, archive_file).await?;
+            download.stats()
+        }
+    };
+    update::process_download(
+        updater,
+        asset,
+        &archive,
+        manifest_hash.as_deref(),
+        signature.as_deref(),
+    )?;
+
+    Ok(StagedAsset {
+        dir: dir.to_owned(),
+        archive,
+        streamed: false,
+        stats,
+    })
+}
+
+// Fetch the checksum manifest of `release` and look up the entry for `asset_name`
+async fn fetch_manifest_checksum<U: ReleaseUpdate + ?Sized>(
+    updater: &U,
+    client: &reqwest::Client,
+    release: &Release,
+    manifest: &str,
+    asset_name: &str,
+) -> Result<String> {
+    let url = update::manifest_url(release, manifest)?;
+    let resp = client
+        .get(&url)
+        .headers(update::api_headers(
+            &updater.auth_token(),
+            &updater.auth_scheme(),
+            &updater.headers(),
+        )?)
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        return Err(Error::Status(resp.status(), url));
+    }
+    update::manifest_entry(&resp.text().await?, asset_name, &url)
+}
+
+// Fetch the detached signature of `asset`
+async fn fetch_signature<U: ReleaseUpdate + ?Sized>(
+    updater: &U,
+    client: &reqwest::Client,
+    asset: &ReleaseAsset,
+) -> Result<String> {
+    let url = update::signature_url(asset)?;
+    let resp = client
+        .get(&url)
+        .headers(update::api_headers(
+            &updater.auth_token(),
+            &updater.auth_scheme(),
+            &updater.headers(),
+        )?)
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        return Err(Error::Status(resp.status(), url));
+    }
+    Ok(resp.text().await?)
+}
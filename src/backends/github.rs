@@ -178,12 +178,14 @@ impl ReleaseList {
             .headers(api_headers(&self.auth_token)?)
             .send()?;
         if !resp.status().is_success() {
-            bail!(
-                Error::Network,
-                "api request failed with status: {:?} - for: {:?}",
-                resp.status(),
-                url
-            )
+            return Err(Error::Network(
+                ErrorKind::Status,
+                format!(
+                    "api request failed with status: {:?} - for: {:?}",
+                    resp.status(),
+                    url
+                ),
+            ))
         }
         let headers = resp.headers().clone();
 
@@ -469,12 +471,14 @@ impl ReleaseUpdate for Update {
             .headers(api_headers(&self.auth_token)?)
             .send()?;
         if !resp.status().is_success() {
-            bail!(
-                Error::Network,
-                "api request failed with status: {:?} - for: {:?}",
-                resp.status(),
-                api_url
-            )
+            return Err(Error::Network(
+                ErrorKind::Status,
+                format!(
+                    "api request failed with status: {:?} - for: {:?}",
+                    resp.status(),
+                    api_url
+                ),
+            ))
         }
         let json = resp.json::<serde_json::Value>()?;
         Ok(Release::from_release(&json)?)
@@ -496,12 +500,14 @@ impl ReleaseUpdate for Update {
             .headers(api_headers(&self.auth_token)?)
             .send()?;
         if !resp.status().is_success() {
-            bail!(
-                Error::Network,
-                "api request failed with status: {:?} - for: {:?}",
-                resp.status(),
-                api_url
-            )
+            return Err(Error::Network(
+                ErrorKind::Status,
+                format!(
+                    "api request failed with status: {:?} - for: {:?}",
+                    resp.status(),
+                    api_url
+                ),
+            ))
         }
         let json = resp.json::<serde_json::Value>()?;
         Ok(Release::from_release(&json)?)
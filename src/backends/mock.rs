@@ -0,0 +1,702 @@
+/*!
+In-memory backend for testing update flows without a server, enabled by the
+`test-util` feature.
+
+Releases are programmed up front with the content of their asset, which is
+"downloaded" from memory, and any phase can be made to fail:
+
+```
+# use update::backends::mock::{MockPhase, MockUpdate};
+# use update::update::ReleaseUpdate;
+let dir = tempfile::tempdir().unwrap();
+let mut mock = MockUpdate::new("app", "1.0.0", dir.path());
+mock.release("1.1.0", b"new app");
+assert!(mock.update().unwrap().updated());
+assert_eq!(std::fs::read(dir.path().join("app")).unwrap(), b"new app");
+
+mock.set_current_version("1.1.0").fail_at(MockPhase::LatestRelease, "offline");
+assert!(mock.update().is_err());
+```
+*/
+use indicatif::ProgressStyle;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::{
+    errors::*,
+    get_target,
+    update::{
+        latest_release, sort_releases, CanApply, ConflictPolicy, HookOutput, Release, ReleaseAsset,
+        ReleaseUpdate, VersionMatch, DEFAULT_HOOK_TIMEOUT,
+    },
+};
+
+/// Phase of an update a `MockUpdate` can be made to fail at
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MockPhase {
+    /// `get_latest_release` and `get_release_list`
+    LatestRelease,
+    /// `get_release_version`
+    ReleaseVersion,
+    /// Serving the asset's content
+    Download,
+    /// `before_update`, before anything is replaced
+    BeforeUpdate,
+    /// `after_update`, once the release is installed
+    AfterUpdate,
+}
+
+/// `ReleaseUpdate` implementation serving canned releases from memory
+#[derive(Debug)]
+pub struct MockUpdate {
+    bin_name: String,
+    current_version: String,
+    target_version: Option<String>,
+    install_dir: PathBuf,
+    releases: Vec<Release>,
+    extra_assets: Vec<(String, PathBuf)>,
+    contents: HashMap<String, Vec<u8>>,
+    failures: HashMap<MockPhase, String>,
+    downloads: Mutex<Vec<String>>,
+    verify_installed_version: bool,
+    verify_version_match: VersionMatch,
+    on_existing_conflict: ConflictPolicy,
+    hook_timeout: Duration,
+    on_complete_webhook: Option<String>,
+    can_apply: Option<(CanApply, Duration)>,
+    retain_on_timeout: bool,
+}
+
+impl MockUpdate {
+    /// Create a backend updating `bin_name` at `current_version`, installed in `install_dir`
+    pub fn new<P: AsRef<Path>>(bin_name: &str, current_version: &str, install_dir: P) -> Self {
+        Self {
+            bin_name: bin_name.to_owned(),
+            current_version: current_version.to_owned(),
+            target_version: None,
+            install_dir: install_dir.as_ref().to_owned(),
+            releases: vec![],
+            extra_assets: vec![],
+            contents: HashMap::new(),
+            failures: HashMap::new(),
+            downloads: Mutex::new(vec![]),
+            verify_installed_version: false,
+            verify_version_match: VersionMatch::Off,
+            on_existing_conflict: ConflictPolicy::Overwrite,
+            hook_timeout: DEFAULT_HOOK_TIMEOUT,
+            on_complete_webhook: None,
+            can_apply: None,
+            retain_on_timeout: false,
+        }
+    }
+
+    /// Add a release of `version` whose asset contains the binary's `content`.
+    /// The asset is named `<bin_name>-<version>` and carries the content's SHA-256 checksum.
+    pub fn release(&mut self, version: &str, content: &[u8]) -> &mut Self {
+        let name = format!("{}-{}", self.bin_name, version);
+        let hash = {
+            use sha2::Digest;
+            format!("{:x}", sha2::Sha256::digest(content))
+        };
+        self.releases.push(Release {
+            name: self.bin_name.clone(),
+            version: version.to_owned(),
+            assets: vec![ReleaseAsset {
+                download_url: format!("mock://{}", name),
+                name: name.clone(),
+                hash: Some(hash),
+                size: Some(content.len() as u64),
+                signature_url: None,
+            }],
+            ..Default::default()
+        });
+        self.contents.insert(name, content.to_vec());
+        self
+    }
+
+    /// Add an asset named `name` with `content` to the release of `version`, added before
+    pub fn asset(&mut self, version: &str, name: &str, content: &[u8]) -> &mut Self {
+        if let Some(release) = self.releases.iter_mut().find(|r| r.version == version) {
+            release.assets.push(ReleaseAsset {
+                download_url: format!("mock://{}", name),
+                name: name.to_owned(),
+                hash: None,
+                size: Some(content.len() as u64),
+                signature_url: None,
+            });
+            self.contents.insert(name.to_owned(), content.to_vec());
+        }
+        self
+    }
+
+    /// Install the asset named `name` to `install_path` along with the binary
+    pub fn extra_asset<P: AsRef<Path>>(&mut self, name: &str, install_path: P) -> &mut Self {
+        self.extra_assets
+            .push((name.to_owned(), install_path.as_ref().to_owned()));
+        self
+    }
+
+    /// Set the current version
+    pub fn set_current_version(&mut self, ver: &str) -> &mut Self {
+        self.current_version = ver.to_owned();
+        self
+    }
+
+    /// Set the version to update to instead of the latest release
+    pub fn set_target_version(&mut self, ver: &str) -> &mut Self {
+        self.target_version = Some(ver.to_owned());
+        self
+    }
+
+    /// Check the installed binary reports the installed version, see
+    /// `ReleaseUpdate::verify_installed_version`
+    pub fn set_verify_installed_version(&mut self, verify: bool) -> &mut Self {
+        self.verify_installed_version = verify;
+        self
+    }
+
+    /// Set the policy applied when the installed binary reports another version, see
+    /// `ReleaseUpdate::verify_version_match`
+    pub fn set_verify_version_match(&mut self, policy: VersionMatch) -> &mut Self {
+        self.verify_version_match = policy;
+        self
+    }
+
+    /// Set the policy applied when the install path doesn't look like the binary, see
+    /// `ReleaseUpdate::on_existing_conflict`
+    pub fn set_on_existing_conflict(&mut self, policy: ConflictPolicy) -> &mut Self {
+        self.on_existing_conflict = policy;
+        self
+    }
+
+    /// Set the time hooks and the installed binary's version check may run, see
+    /// `ReleaseUpdate::hook_timeout`
+    pub fn set_hook_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.hook_timeout = timeout;
+        self
+    }
+
+    /// Notify `url` once a release was installed, see `ReleaseUpdate::on_complete_webhook`
+    pub fn set_on_complete_webhook(&mut self, url: &str) -> &mut Self {
+        self.on_complete_webhook = Some(url.to_owned());
+        self
+    }
+
+    /// Only install a release once `can_apply` allows it, within `timeout`, see
+    /// `ReleaseUpdate::can_apply`
+    pub fn set_can_apply(&mut self, can_apply: CanApply, timeout: Duration) -> &mut Self {
+        self.can_apply = Some((can_apply, timeout));
+        self
+    }
+
+    /// Keep the download for `apply_prefetched` when `can_apply` times out, see
+    /// `ReleaseUpdate::retain_on_timeout`
+    pub fn set_retain_on_timeout(&mut self, retain: bool) -> &mut Self {
+        self.retain_on_timeout = retain;
+        self
+    }
+
+    /// Make `phase` fail with an `Error::Network` of `ErrorKind::Other` carrying `msg`, an
+    /// `Error::Update` for the hooks
+    pub fn fail_at(&mut self, phase: MockPhase, msg: &str) -> &mut Self {
+        self.failures.insert(phase, msg.to_owned());
+        self
+    }
+
+    /// Stop failing at any phase
+    pub fn clear_failures(&mut self) -> &mut Self {
+        self.failures.clear();
+        self
+    }
+
+    /// Names of the assets downloaded so far, in order
+    pub fn downloads(&self) -> Vec<String> {
+        self.downloads.lock().unwrap().clone()
+    }
+
+    fn check(&self, phase: MockPhase) -> Result<()> {
+        match (phase, self.failures.get(&phase)) {
+            (MockPhase::BeforeUpdate, Some(msg)) | (MockPhase::AfterUpdate, Some(msg)) => {
+                bail!(Error::Update, "{}", msg)
+            }
+            (_, Some(msg)) => Err(Error::Network(ErrorKind::Other, msg.to_owned())),
+            (_, None) => Ok(()),
+        }
+    }
+}
+
+impl ReleaseUpdate for MockUpdate {
+    fn get_latest_release(&self) -> Result<Release> {
+        self.check(MockPhase::LatestRelease)?;
+        latest_release(self.releases.clone())
+            .ok_or_else(|| format_err!(Error::Release, "No release found"))
+    }
+
+    fn get_release_version(&self, ver: &str) -> Result<Release> {
+        self.check(MockPhase::ReleaseVersion)?;
+        self.releases
+            .iter()
+            .find(|r| r.version == ver)
+            .cloned()
+            .ok_or_else(|| format_err!(Error::Release, "No release found for version: `{}`", ver))
+    }
+
+    fn get_release_list(&self) -> Result<Vec<Release>> {
+        self.check(MockPhase::LatestRelease)?;
+        Ok(sort_releases(self.releases.clone()))
+    }
+
+    fn current_version(&self) -> String {
+        self.current_version.clone()
+    }
+
+    fn target(&self) -> String {
+        get_target().to_owned()
+    }
+
+    fn target_version(&self) -> Option<String> {
+        self.target_version.clone()
+    }
+
+    fn bin_name(&self) -> String {
+        self.bin_name.clone()
+    }
+
+    fn bin_install_path(&self) -> PathBuf {
+        self.install_dir.join(&self.bin_name)
+    }
+
+    fn extra_assets(&self) -> Vec<(String, PathBuf)> {
+        self.extra_assets.clone()
+    }
+
+    fn bin_path_in_archive(&self) -> PathBuf {
+        PathBuf::from(&self.bin_name)
+    }
+
+    fn show_download_progress(&self) -> bool {
+        false
+    }
+
+    fn show_output(&self) -> bool {
+        false
+    }
+
+    fn no_confirm(&self) -> bool {
+        true
+    }
+
+    fn idty_target_platform(&self) -> bool {
+        false
+    }
+
+    fn progress_style(&self) -> Option<ProgressStyle> {
+        None
+    }
+
+    fn auth_token(&self) -> Option<String> {
+        None
+    }
+
+    fn verify_installed_version(&self) -> bool {
+        self.verify_installed_version
+    }
+
+    fn verify_version_match(&self) -> VersionMatch {
+        self.verify_version_match
+    }
+
+    fn on_existing_conflict(&self) -> ConflictPolicy {
+        self.on_existing_conflict
+    }
+
+    fn hook_timeout(&self) -> Duration {
+        self.hook_timeout
+    }
+
+    fn before_update(&self) -> Result<Option<HookOutput>> {
+        self.check(MockPhase::BeforeUpdate).map(|_| None)
+    }
+
+    fn after_update(&self) -> Result<Option<HookOutput>> {
+        self.check(MockPhase::AfterUpdate).map(|_| None)
+    }
+
+    fn on_complete_webhook(&self) -> Option<String> {
+        self.on_complete_webhook.clone()
+    }
+
+    fn can_apply(&self) -> Option<(CanApply, Duration)> {
+        self.can_apply.clone()
+    }
+
+    fn retain_on_timeout(&self) -> bool {
+        self.retain_on_timeout
+    }
+
+    fn serve_asset(&self, asset: &ReleaseAsset) -> Option<Result<Vec<u8>>> {
+        Some(self.check(MockPhase::Download).and_then(|_| {
+            self.downloads.lock().unwrap().push(asset.name.clone());
+            self.contents.get(&asset.name).cloned().ok_or_else(|| {
+                Error::Network(
+                    ErrorKind::Status,
+                    format!("No content for asset {}", asset.name),
+                )
+            })
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::update::UpdateStatus;
+
+    #[test]
+    fn mock_update_flow() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut mock = MockUpdate::new("app", "1.0.0", dir.path());
+        mock.release("1.1.0", b"v1.1").release("1.2.0", b"v1.2");
+
+        mock.fail_at(MockPhase::Download, "connection reset");
+        let err = mock.update().unwrap_err();
+        assert!(err.to_string().contains("connection reset"), "{}", err);
+        assert!(!dir.path().join("app").exists());
+
+        mock.clear_failures();
+        assert_eq!(mock.update().unwrap().version(), "1.2.0");
+        assert_eq!(std::fs::read(dir.path().join("app")).unwrap(), b"v1.2");
+        assert_eq!(mock.downloads(), vec!["app-1.2.0"]);
+
+        mock.set_current_version("1.2.0");
+        assert!(mock.update().unwrap().uptodate());
+
+        mock.set_target_version("1.1.0");
+        assert!(mock.update().unwrap().updated());
+        assert_eq!(std::fs::read(dir.path().join("app")).unwrap(), b"v1.1");
+    }
+
+    #[test]
+    fn mock_failing_hooks() {
+        let dir = tempfile::tempdir().unwrap();
+        let bin = dir.path().join("app");
+        std::fs::write(&bin, b"v1.0").unwrap();
+        let mut mock = MockUpdate::new("app", "1.0.0", dir.path());
+        mock.release("1.1.0", b"v1.1");
+
+        mock.fail_at(MockPhase::BeforeUpdate, "service didn't stop");
+        let err = mock.update().unwrap_err();
+        assert!(matches!(err, Error::Update(_)), "{}", err);
+        assert_eq!(std::fs::read(&bin).unwrap(), b"v1.0");
+
+        mock.clear_failures()
+            .fail_at(MockPhase::AfterUpdate, "service didn't restart");
+        let err = mock.update().unwrap_err();
+        assert!(err.to_string().contains("didn't restart"), "{}", err);
+        assert_eq!(std::fs::read(&bin).unwrap(), b"v1.0");
+
+        mock.clear_failures();
+        assert!(mock.update().unwrap().updated());
+        assert_eq!(std::fs::read(&bin).unwrap(), b"v1.1");
+    }
+
+    #[test]
+    fn mock_check_for_update() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut mock = MockUpdate::new("app", "1.0.0", dir.path());
+        mock.release("1.1.0", b"v1.1");
+
+        let release = mock.check_for_update().unwrap().unwrap();
+        assert_eq!(release.version, "1.1.0");
+        assert!(mock.downloads().is_empty());
+        assert!(!dir.path().join("app").exists());
+
+        mock.set_current_version("1.1.0");
+        assert!(mock.check_for_update().unwrap().is_none());
+
+        mock.release("1.0.5", b"v1.0.5");
+        let versions = mock
+            .get_release_list()
+            .unwrap()
+            .into_iter()
+            .map(|r| r.version)
+            .collect::<Vec<_>>();
+        assert_eq!(versions, vec!["1.1.0", "1.0.5"]);
+    }
+
+    #[test]
+    fn mock_ensure() {
+        let dir = tempfile::tempdir().unwrap();
+        let bin = dir.path().join("app");
+        std::fs::write(&bin, b"corrupt").unwrap();
+        let mut mock = MockUpdate::new("app", "1.0.0", dir.path());
+        mock.release("1.0.0", b"v1.0");
+
+        assert!(!mock.verify().unwrap().intact());
+        assert!(matches!(mock.ensure().unwrap(), UpdateStatus::Repaired(..)));
+        assert_eq!(std::fs::read(&bin).unwrap(), b"v1.0");
+        assert!(matches!(mock.ensure().unwrap(), UpdateStatus::UpToDate(_)));
+
+        // unsupported checksums can't be verified, nothing is reinstalled
+        std::fs::write(&bin, b"v1.0-patched").unwrap();
+        mock.releases[0].assets[0].hash = Some(format!("sha1:{}", "ab".repeat(20)));
+        let report = mock.verify().unwrap();
+        assert_eq!(report.expected, None);
+        assert!(report.intact());
+        assert!(matches!(mock.ensure().unwrap(), UpdateStatus::UpToDate(_)));
+        assert_eq!(mock.downloads(), vec!["app-1.0.0"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn mock_verify_installed_version() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let bin = dir.path().join("app");
+        let script = |version: &str| format!("#!/bin/sh\necho app {}\n", version);
+        std::fs::write(&bin, script("1.0.0")).unwrap();
+        std::fs::set_permissions(&bin, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let mut mock = MockUpdate::new("app", "1.0.0", dir.path());
+        mock.set_verify_installed_version(true)
+            .release("1.1.0", script("1.0.0").as_bytes())
+            .release("1.2.0", b"#!/bin/sh\nexit 3\n");
+
+        // crashing binary, rolled back
+        let err = mock.update().unwrap_err();
+        assert!(matches!(err, Error::Verify(_)), "{}", err);
+        assert_eq!(std::fs::read_to_string(&bin).unwrap(), script("1.0.0"));
+
+        // wrong version reported, rolled back
+        mock.set_target_version("1.1.0");
+        let err = mock.update().unwrap_err();
+        assert!(matches!(err, Error::Verify(_)), "{}", err);
+        assert!(err.to_string().contains("1.1.0"), "{}", err);
+        assert_eq!(std::fs::read_to_string(&bin).unwrap(), script("1.0.0"));
+
+        // hanging binary, killed and rolled back
+        mock.release("1.2.5", b"#!/bin/sh\nsleep 5\n")
+            .set_target_version("1.2.5")
+            .set_hook_timeout(Duration::from_millis(200));
+        let start = std::time::Instant::now();
+        let err = mock.update().unwrap_err();
+        assert!(matches!(err, Error::Verify(_)), "{}", err);
+        assert!(err.to_string().contains("timed out"), "{}", err);
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(std::fs::read_to_string(&bin).unwrap(), script("1.0.0"));
+
+        mock.release("1.3.0", script("1.3.0").as_bytes())
+            .set_target_version("1.3.0");
+        assert!(mock.update().unwrap().updated());
+        assert_eq!(std::fs::read_to_string(&bin).unwrap(), script("1.3.0"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn mock_verify_version_match() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let bin = dir.path().join("app");
+        std::fs::write(&bin, b"v1.0").unwrap();
+        std::fs::set_permissions(&bin, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let mut mock = MockUpdate::new("app", "1.0.0", dir.path());
+        mock.release("1.1.0", b"#!/bin/sh\nsleep 5\n")
+            .set_verify_version_match(VersionMatch::Warn)
+            .set_hook_timeout(Duration::from_millis(200));
+
+        // a hanging binary is killed, only warned about
+        let start = std::time::Instant::now();
+        assert!(mock.update().unwrap().updated());
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        mock.set_current_version("1.1.0")
+            .release("1.2.0", b"#!/bin/sh\nexit 3\n")
+            .set_verify_version_match(VersionMatch::Fail);
+        assert!(mock.update().is_err());
+        mock.release("1.3.0", b"#!/bin/sh\necho app 1.3.0\n");
+        assert!(mock.update().unwrap().updated());
+    }
+
+    #[test]
+    fn mock_existing_conflict() {
+        let dir = tempfile::tempdir().unwrap();
+        let bin = dir.path().join("app");
+        std::fs::create_dir(&bin).unwrap();
+        let mut mock = MockUpdate::new("app", "1.0.0", dir.path());
+        mock.release("1.1.0", b"v1.1")
+            .set_on_existing_conflict(ConflictPolicy::Fail)
+            .fail_at(MockPhase::BeforeUpdate, "busy");
+
+        // refused before any hook runs
+        let err = mock.update().unwrap_err();
+        assert!(err.to_string().contains("refusing to replace"), "{}", err);
+        assert!(bin.is_dir());
+
+        let mut mock = MockUpdate::new("app", "1.0.0", dir.path());
+        mock.release("1.1.0", b"v1.1")
+            .set_on_existing_conflict(ConflictPolicy::Backup);
+        assert!(mock.update().unwrap().updated());
+        assert_eq!(std::fs::read(&bin).unwrap(), b"v1.1");
+        let moved = std::fs::read_dir(dir.path())
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                entry
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with("app.conflict.")
+            })
+            .count();
+        assert_eq!(moved, 1);
+    }
+
+    #[test]
+    fn mock_complete_webhook() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"}") {
+                let n = conn.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            conn.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        let dir = tempfile::tempdir().unwrap();
+        let mut mock = MockUpdate::new("app", "1.0.0", dir.path());
+        mock.release("1.1.0", b"v1.1").set_on_complete_webhook(&url);
+
+        assert!(mock.update().unwrap().updated());
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /hook"), "{}", request);
+        let body = request.split("\r\n\r\n").nth(1).unwrap();
+        let payload: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(payload["name"], "app");
+        assert_eq!(payload["old_version"], "1.0.0");
+        assert_eq!(payload["new_version"], "1.1.0");
+        assert!(payload["host"].is_string(), "{}", payload);
+
+        // nothing listening anymore, the update still succeeds
+        mock.set_target_version("1.1.0");
+        assert!(mock.update().unwrap().updated());
+    }
+
+    #[test]
+    fn mock_update_extra_assets() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = dir.path().join("app.dat");
+        std::fs::write(&data, b"old data").unwrap();
+        let mut mock = MockUpdate::new("app", "1.0.0", dir.path());
+        mock.release("1.1.0", b"v1.1")
+            .asset("1.1.0", "app.dat", b"new data")
+            .extra_asset("app.dat", &data)
+            .extra_asset("missing.dat", dir.path().join("missing.dat"));
+
+        // nothing is replaced unless every asset is found
+        let err = mock.update().unwrap_err();
+        assert!(err.to_string().contains("missing.dat"), "{}", err);
+        assert!(!dir.path().join("app").exists());
+        assert_eq!(std::fs::read(&data).unwrap(), b"old data");
+
+        mock.extra_assets.pop();
+        assert!(mock.update().unwrap().updated());
+        assert_eq!(mock.downloads(), vec!["app-1.1.0", "app.dat"]);
+        assert_eq!(std::fs::read(dir.path().join("app")).unwrap(), b"v1.1");
+        assert_eq!(std::fs::read(&data).unwrap(), b"new data");
+    }
+
+    #[test]
+    fn mock_retain_on_timeout() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = dir.path().join("app.dat");
+        std::fs::write(&data, b"old data").unwrap();
+        let mut mock = MockUpdate::new("app", "1.0.0", dir.path());
+        mock.release("1.1.0", b"v1.1")
+            .asset("1.1.0", "app.dat", b"new data")
+            .extra_asset("app.dat", &data)
+            .set_can_apply(CanApply::new(|| false), Duration::from_millis(10))
+            .set_retain_on_timeout(true);
+
+        let err = mock.update().unwrap_err();
+        assert!(err.to_string().contains("retained"), "{}", err);
+        assert!(!dir.path().join("app").exists());
+        assert_eq!(std::fs::read(&data).unwrap(), b"old data");
+
+        // the binary and the extra asset are applied together
+        let prefetched = dir.path().join("app_prefetch");
+        assert!(mock.apply_prefetched(&prefetched).unwrap().updated());
+        assert_eq!(std::fs::read(dir.path().join("app")).unwrap(), b"v1.1");
+        assert_eq!(std::fs::read(&data).unwrap(), b"new data");
+        assert!(!prefetched.exists());
+    }
+
+    #[test]
+    fn mock_apply_prefetched() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut mock = MockUpdate::new("app", "1.0.0", dir.path());
+        mock.release("1.1.0", b"v1.1");
+
+        // changed since it was verified, nothing is installed
+        let prefetched = mock.prefetch().unwrap();
+        std::fs::write(prefetched.join("app-1.1.0"), b"v6.6").unwrap();
+        let err = mock.apply_prefetched(&prefetched).err().unwrap();
+        assert!(matches!(err, Error::Checksum(_)), "{}", err);
+        assert!(!dir.path().join("app").exists());
+
+        let prefetched = mock.prefetch().unwrap();
+        match mock.apply_prefetched(&prefetched).unwrap() {
+            UpdateStatus::Updated(old, release, ..) => {
+                assert_eq!(old, "1.0.0");
+                assert_eq!(release.version, "1.1.0");
+            }
+            _ => panic!("not updated"),
+        }
+        assert_eq!(std::fs::read(dir.path().join("app")).unwrap(), b"v1.1");
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn mock_update_async() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let mut mock = MockUpdate::new("app", "1.0.0", dir.path());
+        mock.release("1.1.0", b"v1.1");
+
+        let status = runtime.block_on(mock.update_async()).unwrap();
+        assert_eq!(status.version(), "1.1.0");
+        assert_eq!(std::fs::read(dir.path().join("app")).unwrap(), b"v1.1");
+
+        mock.set_current_version("1.1.0");
+        assert!(runtime.block_on(mock.update_async()).unwrap().uptodate());
+
+        // `can_apply` is waited on without blocking the runtime's only thread
+        use std::sync::atomic::{AtomicBool, Ordering};
+        let allowed = std::sync::Arc::new(AtomicBool::new(false));
+        let check = allowed.clone();
+        mock.release("1.2.0", b"v1.2").set_can_apply(
+            CanApply::new(move || check.load(Ordering::SeqCst)),
+            Duration::from_secs(30),
+        );
+        let start = std::time::Instant::now();
+        let status = runtime.block_on(async {
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                allowed.store(true, Ordering::SeqCst);
+            });
+            mock.update_async().await
+        });
+        assert_eq!(status.unwrap().version(), "1.2.0");
+        assert!(start.elapsed() < Duration::from_secs(30));
+    }
+}
@@ -3,6 +3,8 @@ Collection of modules supporting various release distribution backends
 */
 
 pub mod cloud;
+#[cfg(feature = "test-util")]
+pub mod mock;
 
 /// Search for the first "rel" link-header uri in a full link header string.
 /// Seems like reqwest/hyper threw away their link-header parser implementation...
@@ -14,6 +16,7 @@ pub mod cloud;
 /// https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Link
 /// header values may contain multiple values separated by commas
 /// `Link: <https://place.com>; rel="next", <https://wow.com>; rel="next"`
+#[allow(dead_code)]
 pub(crate) fn find_rel_next_link(link_str: &str) -> Option<&str> {
     for link in link_str.split(',') {
         let mut uri = None;
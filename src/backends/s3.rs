@@ -531,12 +531,14 @@ fn fetch_releases_from_s3(
 
     let resp = reqwest::blocking::Client::new().get(&api_url).send()?;
     if !resp.status().is_success() {
-        bail!(
-            Error::Network,
-            "S3 API request failed with status: {:?} - for: {:?}",
-            resp.status(),
-            api_url
-        )
+        return Err(Error::Network(
+            ErrorKind::Status,
+            format!(
+                "S3 API request failed with status: {:?} - for: {:?}",
+                resp.status(),
+                api_url
+            ),
+        ))
     }
 
     let body = resp.text()?;
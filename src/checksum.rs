@@ -0,0 +1,396 @@
+/*! Checksums
+
+Helpers computing the checksums of downloaded and installed files, and verifying
+downloads against the `Content-MD5` and RFC-3230 `Digest` headers of the response.
+*/
+use crate::errors::*;
+use md5::Md5;
+use reqwest::header::HeaderMap;
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Compute the SHA-256 checksum of the file at `path`, as a lowercase hex string
+pub fn sha256_file<P: AsRef<Path>>(path: P) -> Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Check if `value` looks like a hex encoded SHA-256 checksum
+pub fn is_sha256_hex(value: &str) -> bool {
+    let value = crate::trim_text(value);
+    value.len() == 64 && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Verify the file at `path` against the hex encoded SHA-256 checksum `expected`
+pub fn verify_sha256_file<P: AsRef<Path>>(path: P, expected: &str) -> Result<()> {
+    let expected = crate::trim_text(expected);
+    let actual = sha256_file(&path)?;
+    if !actual.eq_ignore_ascii_case(expected) {
+        bail!(
+            Error::Checksum,
+            "Checksum mismatch for {:?} (sha256), expected: {}, found: {}",
+            path.as_ref(),
+            expected.to_lowercase(),
+            actual
+        )
+    }
+    Ok(())
+}
+
+/// Compute the checksum of the file at `path` with `algorithm`, as a lowercase hex string
+pub fn file_digest<P: AsRef<Path>>(path: P, algorithm: DigestAlgorithm) -> Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Hasher::new(algorithm);
+    let mut buf = [0; 64 * 1024];
+    loop {
+        let n = io::Read::read(&mut file, &mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(to_hex(&hasher.finalize()))
+}
+
+/// Parse a hex encoded checksum and detect its algorithm, from a prefix such as `sha256:`
+/// or `md5:`, or else from its length: 32 digits for MD5, 128 for SHA-512, SHA-256 otherwise.
+/// Returns the algorithm and the lowercase hex digits, `None` if `value` isn't hex encoded.
+pub fn parse_checksum(value: &str) -> Option<(DigestAlgorithm, String)> {
+    let value = crate::trim_text(value);
+    let (algorithm, hex) = match value.find(':') {
+        Some(i) => {
+            let token = value[..i].to_ascii_lowercase().replace('-', "");
+            let algorithm = match token.as_str() {
+                "md5" => DigestAlgorithm::Md5,
+                "sha256" => DigestAlgorithm::Sha256,
+                "sha512" => DigestAlgorithm::Sha512,
+                _ => return None,
+            };
+            (Some(algorithm), value[i + 1..].trim())
+        }
+        None => (None, value),
+    };
+    if hex.is_empty() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let algorithm = algorithm.unwrap_or(match hex.len() {
+        32 => DigestAlgorithm::Md5,
+        128 => DigestAlgorithm::Sha512,
+        _ => DigestAlgorithm::Sha256,
+    });
+    Some((algorithm, hex.to_ascii_lowercase()))
+}
+
+/// Verify the file at `path` against the checksum `expected`, whose algorithm is detected
+/// by `parse_checksum`. Fails with an `Error::Checksum` on mismatch.
+pub fn verify_file<P: AsRef<Path>>(path: P, expected: &str) -> Result<()> {
+    let (algorithm, expected) = parse_checksum(expected).ok_or_else(|| {
+        format_err!(
+            Error::Checksum,
+            "Invalid checksum for {:?}: {:?}",
+            path.as_ref(),
+            expected
+        )
+    })?;
+    let actual = file_digest(&path, algorithm)?;
+    if actual != expected {
+        bail!(
+            Error::Checksum,
+            "Checksum mismatch for {:?} ({}), expected: {}, found: {}",
+            path.as_ref(),
+            algorithm,
+            expected,
+            actual
+        )
+    }
+    Ok(())
+}
+
+/// Parse a checksum manifest (e.g. `checksums.json`), a json object mapping file names
+/// to their checksum. A leading BOM and whitespace around names and checksums are ignored.
+pub fn parse_manifest(body: &str) -> Result<HashMap<String, String>> {
+    let checksums: HashMap<String, String> =
+        serde_json::from_str(crate::trim_text(body)).map_err(|e| {
+            format_err!(
+                Error::Release,
+                "Invalid checksum manifest, expected a json object of file names to checksums: {}",
+                e
+            )
+        })?;
+    Ok(checksums
+        .into_iter()
+        .map(|(name, checksum)| {
+            (
+                crate::trim_text(&name).to_owned(),
+                crate::trim_text(&checksum).to_owned(),
+            )
+        })
+        .collect())
+}
+
+/// Digest algorithms understood in checksums and in `Content-MD5` and `Digest` response headers
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DigestAlgorithm {
+    Md5,
+    Sha256,
+    Sha512,
+}
+
+impl DigestAlgorithm {
+    // Parse an RFC-3230/RFC-5843 algorithm token, e.g. `SHA-256`
+    fn from_token(token: &str) -> Option<Self> {
+        match token.to_ascii_lowercase().as_str() {
+            "md5" => Some(DigestAlgorithm::Md5),
+            "sha-256" => Some(DigestAlgorithm::Sha256),
+            "sha-512" => Some(DigestAlgorithm::Sha512),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for DigestAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            DigestAlgorithm::Md5 => "md5",
+            DigestAlgorithm::Sha256 => "sha256",
+            DigestAlgorithm::Sha512 => "sha512",
+        })
+    }
+}
+
+/// Encode `bytes` as a lowercase hex string
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// A digest of a response body, as announced by the response headers
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeaderDigest {
+    pub algorithm: DigestAlgorithm,
+    pub value: Vec<u8>,
+}
+
+/// Collect the digests announced by the `Content-MD5` and `Digest` headers.
+/// Unsupported algorithms and malformed values are skipped.
+pub fn header_digests(headers: &HeaderMap) -> Vec<HeaderDigest> {
+    let mut digests = vec![];
+    let decode = |value: &str| match base64::decode(value.trim()) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            warn!("Ignoring malformed digest header value:{:?}, {}", value, e);
+            None
+        }
+    };
+
+    for value in headers.get_all("content-md5") {
+        if let Some(value) = value.to_str().ok().and_then(decode) {
+            digests.push(HeaderDigest {
+                algorithm: DigestAlgorithm::Md5,
+                value,
+            });
+        }
+    }
+    for value in headers.get_all("digest") {
+        let value = match value.to_str() {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+        for entry in value.split(',') {
+            let mut parts = entry.trim().splitn(2, '=');
+            let (token, value) = match (parts.next(), parts.next()) {
+                (Some(token), Some(value)) => (token, value),
+                _ => continue,
+            };
+            let algorithm = match DigestAlgorithm::from_token(token) {
+                Some(algorithm) => algorithm,
+                None => continue,
+            };
+            if let Some(value) = decode(value) {
+                digests.push(HeaderDigest { algorithm, value });
+            }
+        }
+    }
+    digests
+}
+
+enum Hasher {
+    Md5(Md5),
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+impl Hasher {
+    fn new(algorithm: DigestAlgorithm) -> Self {
+        match algorithm {
+            DigestAlgorithm::Md5 => Hasher::Md5(Md5::new()),
+            DigestAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            DigestAlgorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Md5(h) => h.update(data),
+            Hasher::Sha256(h) => h.update(data),
+            Hasher::Sha512(h) => h.update(data),
+        }
+    }
+
+    fn finalize(self) -> Vec<u8> {
+        match self {
+            Hasher::Md5(h) => h.finalize().to_vec(),
+            Hasher::Sha256(h) => h.finalize().to_vec(),
+            Hasher::Sha512(h) => h.finalize().to_vec(),
+        }
+    }
+}
+
+/// Incrementally hashes a body, to verify it against the digests announced in its headers
+pub struct DigestVerifier {
+    hashers: Vec<(HeaderDigest, Hasher)>,
+}
+
+impl DigestVerifier {
+    pub fn new(expected: Vec<HeaderDigest>) -> Self {
+        let hashers = expected
+            .into_iter()
+            .map(|digest| {
+                let hasher = Hasher::new(digest.algorithm);
+                (digest, hasher)
+            })
+            .collect();
+        Self { hashers }
+    }
+
+    /// Feed the next chunk of the body
+    pub fn update(&mut self, data: &[u8]) {
+        for (_, hasher) in self.hashers.iter_mut() {
+            hasher.update(data);
+        }
+    }
+
+    /// Check the hashed body against every expected digest
+    pub fn verify(self) -> Result<()> {
+        for (expected, hasher) in self.hashers {
+            let actual = hasher.finalize();
+            if actual != expected.value {
+                bail!(
+                    Error::Checksum,
+                    "Checksum mismatch for the downloaded file ({}), expected: {}, found: {}",
+                    expected.algorithm,
+                    to_hex(&expected.value),
+                    to_hex(&actual)
+                )
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn sha256_of_file() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"hello").unwrap();
+        assert_eq!(
+            sha256_file(file.path()).unwrap(),
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+    }
+
+    #[test]
+    fn verify_sha256() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"hello").unwrap();
+        let hash = "2CF24DBA5FB0A30E26E83B2AC5B9E29E1B161E5C1FA7425E73043362938B9824";
+        assert!(is_sha256_hex(hash));
+        assert!(!is_sha256_hex("5d41402abc4b2a76b9719d911017c592"));
+        assert!(verify_sha256_file(file.path(), hash).is_ok());
+        assert!(verify_sha256_file(file.path(), &format!("\u{feff}{}\r\n", hash)).is_ok());
+        let msg = verify_sha256_file(file.path(), &"0".repeat(64))
+            .unwrap_err()
+            .to_string();
+        assert!(msg.contains("(sha256)"));
+        assert!(msg.contains(&"0".repeat(64)));
+        assert!(msg.contains("2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"));
+    }
+
+    #[test]
+    fn detect_checksum_algorithm() {
+        let md5 = "5d41402abc4b2a76b9719d911017c592";
+        let sha256 = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+        assert_eq!(
+            parse_checksum(md5),
+            Some((DigestAlgorithm::Md5, md5.to_owned()))
+        );
+        assert_eq!(
+            parse_checksum(&format!("SHA-256:{}", sha256.to_uppercase())),
+            Some((DigestAlgorithm::Sha256, sha256.to_owned()))
+        );
+        assert_eq!(
+            parse_checksum(&"a".repeat(128)).map(|(a, _)| a),
+            Some(DigestAlgorithm::Sha512)
+        );
+        assert_eq!(
+            parse_checksum("abc").map(|(a, _)| a),
+            Some(DigestAlgorithm::Sha256)
+        );
+        assert_eq!(parse_checksum("crc32:abc"), None);
+        assert_eq!(parse_checksum("not a checksum"), None);
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"hello").unwrap();
+        assert!(verify_file(file.path(), md5).is_ok());
+        assert!(verify_file(file.path(), &format!("sha256:{}", sha256)).is_ok());
+        let err = verify_file(file.path(), &format!("md5:{}", "0".repeat(32))).unwrap_err();
+        assert!(matches!(err, Error::Checksum(_)));
+        assert!(err.to_string().contains("(md5)"));
+    }
+
+    #[test]
+    fn checksum_manifest() {
+        let manifest = parse_manifest(r#"{"app.tar.gz": "abc", "app.zip": "def"}"#).unwrap();
+        assert_eq!(manifest["app.tar.gz"], "abc");
+        assert_eq!(manifest["app.zip"], "def");
+        assert!(parse_manifest(r#"["app.tar.gz"]"#).is_err());
+
+        let manifest = parse_manifest("\u{feff}{\"app.zip\": \"def\\r\\n\"}\r\n").unwrap();
+        assert_eq!(manifest["app.zip"], "def");
+    }
+
+    #[test]
+    fn verify_header_digests() {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-md5", "XUFAKrxLKna5cZ2REBfFkg==".parse().unwrap());
+        headers.insert(
+            "digest",
+            "unixsum=30637, SHA-256=LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ="
+                .parse()
+                .unwrap(),
+        );
+        let digests = header_digests(&headers);
+        assert_eq!(2, digests.len());
+        assert_eq!(DigestAlgorithm::Md5, digests[0].algorithm);
+        assert_eq!(DigestAlgorithm::Sha256, digests[1].algorithm);
+
+        let mut verifier = DigestVerifier::new(digests.clone());
+        verifier.update(b"hel");
+        verifier.update(b"lo");
+        assert!(verifier.verify().is_ok());
+
+        let mut verifier = DigestVerifier::new(digests);
+        verifier.update(b"hello!");
+        let msg = verifier.verify().unwrap_err().to_string();
+        assert!(msg.contains("(md5)"));
+        assert!(msg.contains("expected: 5d41402abc4b2a76b9719d911017c592"));
+    }
+}
@@ -0,0 +1,217 @@
+/*! Http clients
+
+Settings of the `reqwest` clients used for api requests and downloads, see `ClientOptions`.
+They're applied the same way to the blocking and the async client.
+*/
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::{errors::*, pinning};
+
+/// Minimum TLS protocol version accepted by the http client
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TlsVersion {
+    Tls12,
+    Tls13,
+}
+
+impl TlsVersion {
+    fn to_reqwest(self) -> reqwest::tls::Version {
+        match self {
+            TlsVersion::Tls12 => reqwest::tls::Version::TLS_1_2,
+            TlsVersion::Tls13 => reqwest::tls::Version::TLS_1_3,
+        }
+    }
+}
+
+/// Settings of the `reqwest` client used for api requests and downloads, set through
+/// a backend's builder. Unset options keep `reqwest`'s defaults.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ClientOptions {
+    pub(crate) http2_prior_knowledge: bool,
+    pub(crate) pool_idle_timeout: Option<Duration>,
+    pub(crate) tcp_keepalive: Option<Duration>,
+    pub(crate) min_tls_version: Option<TlsVersion>,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) connect_timeout: Option<Duration>,
+    pub(crate) proxy: Option<String>,
+    pub(crate) no_proxy: Option<String>,
+    pub(crate) ca_bundle: Option<PathBuf>,
+    pub(crate) pinned_cert_sha256: Option<String>,
+    // not handed to the sandboxed download, which builds its own client
+    #[serde(skip)]
+    pub(crate) client: Option<reqwest::blocking::Client>,
+}
+
+// Apply the `options` to `builder`, a blocking or an async `ClientBuilder`, which share
+// the same methods, and build the client
+macro_rules! build_with_options {
+    ($options:expr, $builder:expr) => {{
+        let options: &ClientOptions = $options;
+        if options.ca_bundle.is_none() {
+            set_ssl_vars!();
+        }
+        let mut builder = $builder;
+        if options.http2_prior_knowledge {
+            builder = builder.http2_prior_knowledge();
+        }
+        if let Some(timeout) = options.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+        if let Some(interval) = options.tcp_keepalive {
+            builder = builder.tcp_keepalive(interval);
+        }
+        if let Some(version) = options.min_tls_version {
+            builder = builder.min_tls_version(version.to_reqwest());
+        }
+        if let Some(timeout) = options.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(timeout) = options.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(proxy) = options.build_proxy()? {
+            builder = builder.proxy(proxy);
+        }
+        for cert in options.ca_certificates()? {
+            builder = builder.add_root_certificate(cert);
+        }
+        if let Some(ref pinned) = options.pinned_cert_sha256 {
+            builder = builder
+                .use_preconfigured_tls(options.pinned_tls_config(pinned)?)
+                .https_only(true);
+        }
+        builder
+            .build()
+            .map_err(|e| Error::Config(format!("Failed to build http client: {}", e)))
+    }};
+}
+
+impl ClientOptions {
+    // The caller-supplied client if any, a new client built from the options otherwise.
+    // The pin can't be enforced by a caller-supplied client.
+    pub(crate) fn build_client(&self) -> Result<reqwest::blocking::Client> {
+        match (&self.client, &self.pinned_cert_sha256) {
+            (Some(_), Some(_)) => bail!(
+                Error::Config,
+                "A pinned certificate can't be enforced by a caller-supplied client"
+            ),
+            (Some(client), None) => Ok(client.clone()),
+            (None, _) => self.build_new_client(),
+        }
+    }
+
+    // A new blocking client built from the options, the caller-supplied client is ignored
+    pub(crate) fn build_new_client(&self) -> Result<reqwest::blocking::Client> {
+        build_with_options!(self, reqwest::blocking::Client::builder())
+    }
+
+    // Same as `build_new_client`, building the async client
+    #[cfg(feature = "async")]
+    pub(crate) fn build_async_client(&self) -> Result<reqwest::Client> {
+        build_with_options!(self, reqwest::Client::builder())
+    }
+
+    // Root certificates of the `ca_bundle`, trusted in addition to the built-in ones
+    fn ca_certificates(&self) -> Result<Vec<reqwest::Certificate>> {
+        let (path, pem) = match self.read_ca_bundle()? {
+            Some(bundle) => bundle,
+            None => return Ok(vec![]),
+        };
+        let certs = reqwest::Certificate::from_pem_bundle(&pem)
+            .map_err(|e| format_err!(Error::Config, "Invalid CA bundle {:?}: {}", path, e))?;
+        if certs.is_empty() {
+            bail!(
+                Error::Config,
+                "No certificate found in CA bundle {:?}",
+                path
+            )
+        }
+        Ok(certs)
+    }
+
+    // Path and PEM content of the `ca_bundle`, if set
+    fn read_ca_bundle(&self) -> Result<Option<(&PathBuf, Vec<u8>)>> {
+        let path = match self.ca_bundle {
+            Some(ref path) => path,
+            None => return Ok(None),
+        };
+        let pem = std::fs::read(path).map_err(|e| {
+            format_err!(Error::Config, "Failed to read CA bundle {:?}: {}", path, e)
+        })?;
+        Ok(Some((path, pem)))
+    }
+
+    // TLS config enforcing the `pinned` fingerprint during the handshake. It replaces the
+    // client's own, so the `ca_bundle` and `min_tls_version` are applied to it.
+    #[cfg(feature = "rustls-tls")]
+    fn pinned_tls_config(&self, pinned: &str) -> Result<rustls::ClientConfig> {
+        let mut roots = vec![];
+        if let Some((path, pem)) = self.read_ca_bundle()? {
+            roots = rustls_pemfile::certs(&mut pem.as_slice())
+                .map_err(|e| format_err!(Error::Config, "Invalid CA bundle {:?}: {}", path, e))?;
+            if roots.is_empty() {
+                bail!(
+                    Error::Config,
+                    "No certificate found in CA bundle {:?}",
+                    path
+                )
+            }
+        }
+        let tls13_only = self.min_tls_version == Some(TlsVersion::Tls13);
+        pinning::tls_config(pinned, &roots, tls13_only)
+    }
+
+    // Without `rustls`, the pin can't be checked during the handshake
+    #[cfg(not(feature = "rustls-tls"))]
+    fn pinned_tls_config(&self, pinned: &str) -> Result<()> {
+        pinning::fingerprint(pinned)?;
+        bail!(
+            Error::Config,
+            "Certificate pinning requires the `rustls-tls` feature"
+        )
+    }
+
+    // The proxy all requests go through, if set. Without it `reqwest` uses the proxy
+    // set by the `HTTP_PROXY`/`HTTPS_PROXY` env vars.
+    fn build_proxy(&self) -> Result<Option<reqwest::Proxy>> {
+        let url = match self.proxy {
+            Some(ref url) => url,
+            None => return Ok(None),
+        };
+        let proxy = reqwest::Proxy::all(url)
+            .map_err(|e| format_err!(Error::Config, "Invalid proxy url {:?}: {}", url, e))?;
+        Ok(Some(match self.no_proxy {
+            Some(ref hosts) => proxy.no_proxy(reqwest::NoProxy::from_string(hosts)),
+            None => proxy,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_with_options() {
+        let mut options = ClientOptions {
+            timeout: Some(Duration::from_secs(5)),
+            min_tls_version: Some(TlsVersion::Tls12),
+            proxy: Some("http://127.0.0.1:3128".into()),
+            ..Default::default()
+        };
+        options.build_new_client().unwrap();
+        #[cfg(feature = "async")]
+        options.build_async_client().unwrap();
+
+        options.ca_bundle = Some(PathBuf::from("/no/such/bundle.pem"));
+        let err = options.build_new_client().unwrap_err();
+        assert!(matches!(err, Error::Config(_)), "{}", err);
+        #[cfg(feature = "async")]
+        {
+            let err = options.build_async_client().unwrap_err();
+            assert!(matches!(err, Error::Config(_)), "{}", err);
+        }
+    }
+}
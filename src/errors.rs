@@ -10,9 +10,27 @@ pub type Result<T> = std::result::Result<T, Error>;
 #[derive(Debug)]
 pub enum Error {
     Update(String),
-    Network(String),
+    /// A request failed, carrying the category of the failure
+    Network(ErrorKind, String),
+    /// A request was answered with an unsuccessful status, carrying the status and the
+    /// requested url
+    Status(reqwest::StatusCode, String),
     Release(String),
     Config(String),
+    /// A downloaded or installed file doesn't match its expected checksum
+    Checksum(String),
+    /// A download's signature doesn't verify against the verifying key
+    Signature(String),
+    /// The server's certificate doesn't match the pinned fingerprint
+    Tls(String),
+    /// The installed binary doesn't run or doesn't report the installed version
+    Verify(String),
+    /// A download was cut short, or otherwise doesn't match its response headers
+    Download(String),
+    /// Not enough free disk space for a download
+    InsufficientSpace(String),
+    /// The download was cancelled through the updater's cancel flag
+    Cancelled,
     Io(std::io::Error),
     #[cfg(feature = "archive-zip")]
     Zip(ZipError),
@@ -22,14 +40,154 @@ pub enum Error {
     ArchiveNotEnabled(String),
 }
 
+/// Category of a network failure, used to decide if a request is worth retrying
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorKind {
+    /// The request or the connection timed out
+    Timeout,
+    /// The connection couldn't be established
+    Connect,
+    /// The host name couldn't be resolved
+    Dns,
+    /// The TLS handshake or certificate validation failed
+    Tls,
+    /// The response body couldn't be decoded
+    Decode,
+    /// The server answered with an unsuccessful status
+    Status,
+    /// Any other failure, e.g. building the request
+    Other,
+}
+
+impl ErrorKind {
+    /// Returns `true` for transient failures worth retrying: timeouts, connection
+    /// and name resolution failures
+    pub fn is_retryable(self) -> bool {
+        matches!(
+            self,
+            ErrorKind::Timeout | ErrorKind::Connect | ErrorKind::Dns
+        )
+    }
+}
+
+/// Classify a `reqwest::Error`, looking through its sources for DNS and TLS failures
+pub(crate) fn classify(e: &reqwest::Error) -> ErrorKind {
+    if e.is_timeout() {
+        return ErrorKind::Timeout;
+    }
+    if e.is_status() {
+        return ErrorKind::Status;
+    }
+    if e.is_decode() {
+        return ErrorKind::Decode;
+    }
+
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(e);
+    while let Some(err) = source {
+        if let Some(io) = err.downcast_ref::<std::io::Error>() {
+            if io.kind() == std::io::ErrorKind::TimedOut {
+                return ErrorKind::Timeout;
+            }
+        }
+        let msg = err.to_string().to_lowercase();
+        if msg.contains("dns error") || msg.contains("failed to lookup address") {
+            return ErrorKind::Dns;
+        }
+        if msg.contains("certificate") || msg.contains("tls") || msg.contains("ssl") {
+            return ErrorKind::Tls;
+        }
+        source = err.source();
+    }
+
+    if e.is_connect() {
+        ErrorKind::Connect
+    } else {
+        ErrorKind::Other
+    }
+}
+
+/// Surface a request timeout as `Error::Network` saying so, a server certificate not matching
+/// the pin as `Error::Tls`, other failures as `Error::Reqwest`
+pub(crate) fn request_error(e: reqwest::Error, url: &str) -> Error {
+    if classify(&e) == ErrorKind::Timeout {
+        Error::Network(
+            ErrorKind::Timeout,
+            format!("request timed out - for: {:?}: {}", url, e),
+        )
+    } else {
+        Error::from(e)
+    }
+}
+
+impl Error {
+    /// Category of the network failure, `None` if the error isn't network related
+    pub fn network_kind(&self) -> Option<ErrorKind> {
+        match *self {
+            Error::Reqwest(ref e) => Some(classify(e)),
+            Error::Network(kind, _) => Some(kind),
+            Error::Status(..) => Some(ErrorKind::Status),
+            Error::Tls(_) => Some(ErrorKind::Tls),
+            _ => None,
+        }
+    }
+
+    /// The unsuccessful status a request was answered with, e.g. to prompt for
+    /// credentials on a `401` or retry on a `5xx`
+    pub fn status(&self) -> Option<reqwest::StatusCode> {
+        match *self {
+            Error::Status(status, _) => Some(status),
+            Error::Reqwest(ref e) => e.status(),
+            _ => None,
+        }
+    }
+
+    /// Name of the error's category (its variant), e.g. `Network`
+    pub fn kind(&self) -> &'static str {
+        use Error::*;
+        match *self {
+            Update(_) => "Update",
+            Network(..) => "Network",
+            Status(..) => "Status",
+            Release(_) => "Release",
+            Config(_) => "Config",
+            Checksum(_) => "Checksum",
+            Signature(_) => "Signature",
+            Tls(_) => "Tls",
+            Verify(_) => "Verify",
+            Download(_) => "Download",
+            InsufficientSpace(_) => "InsufficientSpace",
+            Cancelled => "Cancelled",
+            Io(_) => "Io",
+            #[cfg(feature = "archive-zip")]
+            Zip(_) => "Zip",
+            Json(_) => "Json",
+            Reqwest(_) => "Reqwest",
+            SemVer(_) => "SemVer",
+            ArchiveNotEnabled(_) => "ArchiveNotEnabled",
+        }
+    }
+}
+
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         use Error::*;
         match *self {
             Update(ref s) => write!(f, "UpdateError: {}", s),
-            Network(ref s) => write!(f, "NetworkError: {}", s),
+            Network(_, ref s) => write!(f, "NetworkError: {}", s),
+            Status(status, ref url) => write!(
+                f,
+                "StatusError: request failed with status: {} - for: {:?}",
+                status, url
+            ),
             Release(ref s) => write!(f, "ReleaseError: {}", s),
             Config(ref s) => write!(f, "ConfigError: {}", s),
+            Checksum(ref s) => write!(f, "ChecksumError: {}", s),
+            Signature(ref s) => write!(f, "SignatureError: {}", s),
+            Tls(ref s) => write!(f, "TlsError: {}", s),
+            Verify(ref s) => write!(f, "VerifyError: {}", s),
+            Download(ref s) => write!(f, "DownloadError: {}", s),
+            InsufficientSpace(ref s) => write!(f, "InsufficientSpace: {}", s),
+            Cancelled => write!(f, "Cancelled: the download was cancelled"),
             Io(ref e) => write!(f, "IoError: {}", e),
             Json(ref e) => write!(f, "JsonError: {}", e),
             Reqwest(ref e) => write!(f, "ReqwestError: {}", e),
@@ -83,7 +241,7 @@ impl From<serde_json::Error> for Error {
 
 impl From<reqwest::Error> for Error {
     fn from(e: reqwest::Error) -> Error {
-        Error::Reqwest(e)
+        crate::pinning::mismatch(&e).unwrap_or(Error::Reqwest(e))
     }
 }
 
@@ -99,3 +257,29 @@ impl From<ZipError> for Error {
         Error::Zip(e)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_network_errors() {
+        let e = reqwest::blocking::get("http://127.0.0.1:1").unwrap_err();
+        assert_eq!(classify(&e), ErrorKind::Connect);
+        assert!(classify(&e).is_retryable());
+
+        let err = Error::from(e);
+        assert_eq!(err.network_kind(), Some(ErrorKind::Connect));
+        assert_eq!(
+            Error::Network(ErrorKind::Status, "status: 503".into()).network_kind(),
+            Some(ErrorKind::Status)
+        );
+        assert_eq!(Error::Update("failed".into()).network_kind(), None);
+
+        let err = Error::Status(reqwest::StatusCode::UNAUTHORIZED, "http://host".into());
+        assert_eq!(err.network_kind(), Some(ErrorKind::Status));
+        assert_eq!(err.status(), Some(reqwest::StatusCode::UNAUTHORIZED));
+        assert_eq!(Error::Update("failed".into()).status(), None);
+        assert!(!ErrorKind::Tls.is_retryable());
+    }
+}
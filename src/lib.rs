@@ -1,4 +1,4 @@
-#![cfg_attr(feature = "cargo-clippy", deny(clippy::all))]
+#![deny(clippy::all)]
 /*!
 
 [![Build status](https://ci.appveyor.com/api/projects/status/xlkq8rd73cla4ixw/branch/master?svg=true)](https://ci.appveyor.com/project/jaemk/self-update/branch/master)
@@ -12,22 +12,29 @@ distribution backends.
 
 ## Usage
 
-Update (replace) the current executable with the latest release downloaded
-from `https://api.github.com/repos/jaemk/self_update/releases/latest`.
+Update (replace) the current executable with the latest release served by the cloud api
+(`<custom_url>/api/soft/getver` by default, see `version_path_template`).
 Note, the [`trust`](https://github.com/japaric/trust) project provides a nice setup for
 producing release-builds via CI (travis/appveyor).
 
 ### Features
 
 The following [cargo features](https://doc.rust-lang.org/cargo/reference/manifest.html#the-features-section) are
-available (but _disabled_ by default):
+available (`rustls-tls`, `archive-zip`, `archive-tar` and `compression-flate2` are enabled by default):
 
 * `archive-tar`: Support for _tar_ archive format;
 * `archive-zip`: Support for _zip_ archive format;
 * `compression-flate2`: Support for _gzip_ compression;
+* `compression-xz`: Support for _xz_ compression, e.g. `.tar.xz` archives;
+* `compression-bzip2`: Support for _bzip2_ compression, e.g. `.tar.bz2` archives;
 * `compression-zip-deflate`: Support for _zip_'s _deflate_ compression format;
 * `compression-zip-bzip2`: Support for _zip_'s _bzip2_ compression format;
-* `rustls`: Use [pure rust TLS implementation](https://github.com/ctz/rustls) for network requests. This feature does _not_ support 32bit macOS;
+* `rustls-tls`: Use [pure rust TLS implementation](https://github.com/ctz/rustls) for network requests. No OpenSSL is needed, so e.g. static musl builds work out of the box. This feature does _not_ support 32bit macOS;
+* `native-tls`: Use the platform's native TLS implementation (OpenSSL on linux) for network requests instead. Enabling both TLS features fails the build, disable the default features to use `native-tls`;
+* `rustls`: Deprecated alias of `rustls-tls`;
+* `socks`: Support for `socks5://` proxy urls in the builders' `proxy` setting;
+* `signatures`: Verification of the downloads' [minisign](https://jedisct1.github.io/minisign/) signatures against the builder's `verifying_key`;
+* `async`: `ReleaseUpdate::update_async`, fetching releases and downloading through `reqwest`'s async client. Requires a [tokio](https://tokio.rs) runtime;
 
 Please activate the feature(s) needed by your release files.
 
@@ -35,22 +42,18 @@ Please activate the feature(s) needed by your release files.
 
 Run the following example to see `self_update` in action:
 
-`cargo run --example github --features "archive-tar archive-zip compression-flate2 compression-zip-deflate"`.
-
-There's also an equivalent example for gitlab:
-
-`cargo run --example gitlab --features "archive-tar archive-zip compression-flate2 compression-zip-deflate"`.
+`cargo run --example cloud --features "archive-tar archive-zip compression-flate2 compression-zip-deflate"`.
 
 which runs something roughly equivalent to:
 
 ```rust
-use self_update::cargo_crate_version;
+use update::cargo_crate_version;
 
-fn update() -> Result<(), Box<::std::error::Error>> {
-    let status = self_update::backends::github::Update::configure()
-        .repo_owner("jaemk")
-        .repo_name("self_update")
-        .bin_name("github")
+fn update() -> Result<(), Box<dyn ::std::error::Error>> {
+    let status = update::backends::cloud::Update::configure()
+        .name("cloud")
+        .custom_url("http://127.0.0.1:5000")
+        .bin_name("cloud")
         .show_download_progress(true)
         .current_version(cargo_crate_version!())
         .build()?
@@ -60,39 +63,14 @@ fn update() -> Result<(), Box<::std::error::Error>> {
 }
 ```
 
-Amazon S3, Google GCS, and DigitalOcean Spaces are also supported through the `S3` backend to check for new releases. Provided a `bucket_name`
-and `asset_prefix` string, `self_update` will look up all matching files using the following format
-as a convention for the filenames: `[directory/]<asset name>-<semver>-<platform/target>.<extension>`.
-Leading directories will be stripped from the file name allowing the use of subdirectories in the S3 bucket,
-and any file not matching the format, or not matching the provided prefix string, will be ignored.
-
-```rust
-use self_update::cargo_crate_version;
-
-fn update() -> Result<(), Box<::std::error::Error>> {
-    let status = self_update::backends::s3::Update::configure()
-        .bucket_name("self_update_releases")
-        .asset_prefix("something/self_update")
-        .region("eu-west-2")
-        .bin_name("self_update_example")
-        .show_download_progress(true)
-        .current_version(cargo_crate_version!())
-        .build()?
-        .update()?;
-    println!("S3 Update status: `{}`!", status.version());
-    Ok(())
-}
-```
-
 Separate utilities are also exposed (**NOTE**: the following example _requires_ the `archive-tar` feature,
 see the [features](#features) section above):
 
 ```rust
 # #[cfg(feature = "archive-tar")]
-fn update() -> Result<(), Box<::std::error::Error>> {
-    let releases = self_update::backends::github::ReleaseList::configure()
-        .repo_owner("jaemk")
-        .repo_name("self_update")
+fn update() -> Result<(), Box<dyn ::std::error::Error>> {
+    let releases = update::backends::cloud::ReleaseList::configure()
+        .custom_url("http://127.0.0.1:5000")
         .build()?
         .fetch()?;
     println!("found releases:");
@@ -100,7 +78,7 @@ fn update() -> Result<(), Box<::std::error::Error>> {
 
     // get the first available release
     let asset = releases[0]
-        .asset_for(&self_update::get_target()).unwrap();
+        .asset_for(&update::get_target()).unwrap();
 
     let tmp_dir = tempfile::Builder::new()
             .prefix("self_update")
@@ -108,20 +86,20 @@ fn update() -> Result<(), Box<::std::error::Error>> {
     let tmp_tarball_path = tmp_dir.path().join(&asset.name);
     let tmp_tarball = ::std::fs::File::open(&tmp_tarball_path)?;
 
-    self_update::Download::from_url(&asset.download_url)
+    update::Download::from_url(&asset.download_url)
         .set_header(reqwest::header::ACCEPT, "application/octet-stream".parse()?)
         .download_to(&tmp_tarball)?;
 
     let bin_name = std::path::PathBuf::from("self_update_bin");
-    self_update::Extract::from_source(&tmp_tarball_path)
-        .archive(self_update::ArchiveKind::Tar(Some(self_update::Compression::Gz)))
+    update::Extract::from_source(&tmp_tarball_path)
+        .archive(update::ArchiveKind::Tar(Some(update::Compression::Gz)))
         .extract_file(&tmp_dir.path(), &bin_name)?;
 
     let tmp_file = tmp_dir.path().join("replacement_tmp");
     let bin_path = tmp_dir.path().join(bin_name);
-    self_update::Move::from_source(&bin_path)
+    update::Move::from_source(&bin_path)
         .replace_using_temp(&tmp_file)
-        .to_dest(&::std::env::current_exe()?)?;
+        .to_dest(&::std::env::current_exe()?, false)?;
 
     Ok(())
 }
@@ -129,27 +107,45 @@ fn update() -> Result<(), Box<::std::error::Error>> {
 
 */
 
+#[cfg(all(feature = "rustls-tls", feature = "native-tls"))]
+compile_error!(
+    "the `rustls-tls` and `native-tls` features are mutually exclusive, \
+     disable the default features to use `native-tls`"
+);
+
 pub use tempfile::TempDir;
 
 extern crate fs_extra;
 use fs_extra::dir::*;
 
-#[cfg(feature = "compression-flate2")]
-use either::Either;
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::ProgressStyle;
+use progress::ProgressReporter;
 use reqwest::header;
-use std::cmp::min;
 use std::fs;
 use std::io;
 use std::path;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+use std::time::{Duration, Instant};
 
 #[macro_use]
 extern crate log;
 
 #[macro_use]
 mod macros;
+#[cfg(feature = "async")]
+mod async_update;
 pub mod backends;
+pub mod checksum;
+pub mod client;
 pub mod errors;
+mod pinning;
+pub mod progress;
+pub mod sandbox;
+pub mod service;
+mod signature;
 pub mod update;
 pub mod version;
 
@@ -157,7 +153,10 @@ use errors::*;
 
 /// Get the current target triple.
 ///
-/// Returns a target triple (e.g. `x86_64-unknown-linux-gnu` or `i686-pc-windows-msvc`)
+/// Returns a target triple (e.g. `x86_64-unknown-linux-gnu` or `i686-pc-windows-msvc`).
+/// This is the exact triple the crate was compiled for, as passed by cargo to the build
+/// script, so e.g. `aarch64-apple-darwin`, `aarch64-unknown-linux-musl` and
+/// `armv7-unknown-linux-gnueabihf` builds detect their own target.
 pub fn get_target() -> &'static str {
     env!("TARGET")
 }
@@ -180,10 +179,58 @@ pub fn should_update(current: &str, latest: &str) -> Result<bool> {
 ///     * Io flushing
 ///     * User entered anything other than enter/Y/y
 fn confirm(msg: &str) -> Result<()> {
-    print_flush!("{}", msg);
+    let stdin = io::stdin();
+    confirm_with(msg, &mut stdin.lock(), &mut io::stdout())
+}
+
+/// Same as `confirm`, writing `msg` to `writer` and reading the response from `reader`
+///
+/// * Errors:
+///     * Io writing or reading
+///     * User entered anything other than enter/Y/y
+fn confirm_with(msg: &str, reader: &mut dyn io::BufRead, writer: &mut dyn io::Write) -> Result<()> {
+    write!(writer, "{}", msg)?;
+    writer.flush()?;
 
     let mut s = String::new();
-    io::stdin().read_line(&mut s)?;
+    reader.read_line(&mut s)?;
+    check_answer(&s)
+}
+
+/// Same as `confirm`, but falls back to `default_answer` if nothing was entered
+/// after `timeout`. The pending read of stdin is left running in the background.
+///
+/// * Errors:
+///     * Io flushing
+///     * User entered anything other than enter/Y/y
+///     * The timeout expired and `default_answer` is `false`
+fn confirm_timeout(msg: &str, timeout: std::time::Duration, default_answer: bool) -> Result<()> {
+    print_flush!("{}", msg);
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let mut s = String::new();
+        let res = io::stdin().read_line(&mut s).map(|_| s);
+        let _ = tx.send(res);
+    });
+    match rx.recv_timeout(timeout) {
+        Ok(res) => check_answer(&res?),
+        Err(_) => {
+            println!();
+            info!(
+                "No confirmation after {:?}, defaulting to: {}",
+                timeout, default_answer
+            );
+            if !default_answer {
+                bail!(Error::Update, "Update aborted, confirmation timed out");
+            }
+            Ok(())
+        }
+    }
+}
+
+// Interpret a blank response or `y` as yes
+fn check_answer(s: &str) -> Result<()> {
     let s = s.trim().to_lowercase();
     if !s.is_empty() && s != "y" {
         bail!(Error::Update, "Update aborted");
@@ -191,13 +238,23 @@ fn confirm(msg: &str) -> Result<()> {
     Ok(())
 }
 
+/// Strip a leading UTF-8 byte order mark and surrounding whitespace (e.g. a trailing CRLF)
+/// from the contents of a text file, such as a version or checksum file
+pub fn trim_text(text: &str) -> &str {
+    text.trim_start_matches('\u{feff}').trim()
+}
+
 /// Status returned after updating
 ///
 /// Wrapped `String`s are version tags
 #[derive(Debug, Clone)]
 pub enum Status {
     UpToDate(String),
-    Updated(String),
+    /// Updated to the contained version, with the stats of the download when the
+    /// release was downloaded by this update (not prefetched or served from memory),
+    /// and the output of the hook commands that ran
+    Updated(String, Option<DownloadStats>, Vec<update::HookOutput>),
+    DryRun(update::UpdatePlan),
 }
 impl Status {
     /// Return the version tag
@@ -205,7 +262,25 @@ impl Status {
         use Status::*;
         match *self {
             UpToDate(ref s) => s,
-            Updated(ref s) => s,
+            Updated(ref s, ..) => s,
+            DryRun(ref plan) => &plan.target_version,
+        }
+    }
+
+    /// Return the stats of the update's download, `None` unless `Status::Updated`
+    pub fn download_stats(&self) -> Option<&DownloadStats> {
+        match *self {
+            Status::Updated(_, ref stats, _) => stats.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Return the output of the `before_update`/`after_update` hook commands that ran,
+    /// empty unless `Status::Updated`
+    pub fn hook_outputs(&self) -> &[update::HookOutput] {
+        match *self {
+            Status::Updated(_, _, ref hooks) => hooks,
+            _ => &[],
         }
     }
 
@@ -216,7 +291,12 @@ impl Status {
 
     /// Returns `true` if `Status::Updated`
     pub fn updated(&self) -> bool {
-        matches!(*self, Status::Updated(_))
+        matches!(*self, Status::Updated(..))
+    }
+
+    /// Returns `true` if `Status::DryRun`
+    pub fn dry_run(&self) -> bool {
+        matches!(*self, Status::DryRun(_))
     }
 }
 
@@ -225,11 +305,44 @@ impl std::fmt::Display for Status {
         use Status::*;
         match *self {
             UpToDate(ref s) => write!(f, "UpToDate({})", s),
-            Updated(ref s) => write!(f, "Updated({})", s),
+            Updated(ref s, ..) => write!(f, "Updated({})", s),
+            DryRun(ref plan) => write!(f, "DryRun({})", plan.target_version),
         }
     }
 }
 
+/// Metrics of a download, e.g. for telemetry
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DownloadStats {
+    /// Bytes received, not counting those of a resumed partial download
+    pub bytes: u64,
+    /// Time taken from the response to its last byte
+    pub duration: Duration,
+    /// Average throughput, in bytes per second
+    pub avg_bps: u64,
+}
+
+impl DownloadStats {
+    pub fn new(bytes: u64, duration: Duration) -> Self {
+        let secs = duration.as_secs_f64();
+        let avg_bps = if secs > 0.0 {
+            (bytes as f64 / secs) as u64
+        } else {
+            0
+        };
+        DownloadStats {
+            bytes,
+            duration,
+            avg_bps,
+        }
+    }
+
+    /// Combined stats of two downloads made one after the other
+    pub fn combine(self, other: DownloadStats) -> Self {
+        DownloadStats::new(self.bytes + other.bytes, self.duration + other.duration)
+    }
+}
+
 /// Supported archive formats
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ArchiveKind {
@@ -243,6 +356,55 @@ pub enum ArchiveKind {
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Compression {
     Gz,
+    /// Requires the `compression-xz` feature
+    Xz,
+    /// Requires the `compression-bzip2` feature
+    Bz2,
+}
+
+impl Compression {
+    // Compression of the file extension `ext`, e.g. `Gz` for `gz`
+    fn from_extension(ext: &std::ffi::OsStr) -> Option<Self> {
+        match ext.to_str()? {
+            "gz" => Some(Compression::Gz),
+            "xz" => Some(Compression::Xz),
+            "bz2" => Some(Compression::Bz2),
+            _ => None,
+        }
+    }
+}
+
+/// Archive formats (as file extensions) that can be extracted by this build.
+/// Plain, uncompressed files are always supported and aren't listed.
+static SUPPORTED_ARCHIVE_FORMATS: &[&str] = &[
+    #[cfg(feature = "archive-zip")]
+    "zip",
+    #[cfg(feature = "archive-tar")]
+    "tar",
+    #[cfg(all(feature = "archive-tar", feature = "compression-flate2"))]
+    "tar.gz",
+    #[cfg(all(feature = "archive-tar", feature = "compression-flate2"))]
+    "tgz",
+    #[cfg(feature = "compression-flate2")]
+    "gz",
+    #[cfg(all(feature = "archive-tar", feature = "compression-xz"))]
+    "tar.xz",
+    #[cfg(all(feature = "archive-tar", feature = "compression-xz"))]
+    "txz",
+    #[cfg(feature = "compression-xz")]
+    "xz",
+    #[cfg(all(feature = "archive-tar", feature = "compression-bzip2"))]
+    "tar.bz2",
+    #[cfg(all(feature = "archive-tar", feature = "compression-bzip2"))]
+    "tbz2",
+    #[cfg(feature = "compression-bzip2")]
+    "bz2",
+];
+
+/// Return the archive formats (as file extensions, e.g. `zip` or `tar.gz`) supported
+/// by the compiled-in cargo features.
+pub fn supported_archive_formats() -> &'static [&'static str] {
+    SUPPORTED_ARCHIVE_FORMATS
 }
 
 fn detect_archive(path: &path::Path) -> Result<ArchiveKind> {
@@ -274,35 +436,32 @@ fn detect_archive(path: &path::Path) -> Result<ArchiveKind> {
             }
         }
         Some(extension) if extension == std::ffi::OsStr::new("tgz") => {
-            #[cfg(feature = "archive-tar")]
-            {
-                debug!("Detected .tgz archive");
-                Ok(ArchiveKind::Tar(Some(Compression::Gz)))
-            }
-            #[cfg(not(feature = "archive-tar"))]
-            {
-                Err(Error::ArchiveNotEnabled("tar".to_string()))
-            }
+            debug!("Detected .tgz archive");
+            tar_archive(Some(Compression::Gz))
         }
-        Some(extension) if extension == std::ffi::OsStr::new("gz") => match path
-            .file_stem()
-            .map(|e| path::Path::new(e))
-            .and_then(|f| f.extension())
-        {
-            Some(extension) if extension == std::ffi::OsStr::new("tar") => {
-                #[cfg(feature = "archive-tar")]
-                {
-                    debug!("Detected .tar.gz archive");
-                    Ok(ArchiveKind::Tar(Some(Compression::Gz)))
-                }
-                #[cfg(not(feature = "archive-tar"))]
-                {
-                    Err(Error::ArchiveNotEnabled("tar".to_string()))
+        Some(extension) if extension == std::ffi::OsStr::new("txz") => {
+            debug!("Detected .txz archive");
+            tar_archive(Some(Compression::Xz))
+        }
+        Some(extension) if extension == std::ffi::OsStr::new("tbz2") => {
+            debug!("Detected .tbz2 archive");
+            tar_archive(Some(Compression::Bz2))
+        }
+        Some(extension) => match Compression::from_extension(extension) {
+            Some(compression) => match path
+                .file_stem()
+                .map(path::Path::new)
+                .and_then(|f| f.extension())
+            {
+                Some(extension) if extension == std::ffi::OsStr::new("tar") => {
+                    debug!("Detected compressed .tar archive");
+                    tar_archive(Some(compression))
                 }
-            }
-            _ => Ok(ArchiveKind::Plain(Some(Compression::Gz))),
+                _ => Ok(ArchiveKind::Plain(Some(compression))),
+            },
+            None => Ok(ArchiveKind::Plain(None)),
         },
-        _ => Ok(ArchiveKind::Plain(None)),
+        None => Ok(ArchiveKind::Plain(None)),
     };
 
     debug!("Detected archive type: {:?}", res);
@@ -310,6 +469,83 @@ fn detect_archive(path: &path::Path) -> Result<ArchiveKind> {
     res
 }
 
+// Detect the archive format of the file at `path` from its content (magic bytes), e.g. for
+// assets downloaded from extension-less urls, falling back on its extension when the
+// content isn't recognized
+fn detect_source_archive(path: &path::Path) -> Result<ArchiveKind> {
+    match sniff_archive(path)? {
+        Some(kind) => {
+            debug!("Detected archive type from content: {:?}", kind);
+            Ok(kind)
+        }
+        None => detect_archive(path),
+    }
+}
+
+// Detect the archive format from the magic bytes at the start of the file at `path`,
+// `None` when unrecognized, e.g. for a raw binary
+fn sniff_archive(path: &path::Path) -> Result<Option<ArchiveKind>> {
+    use io::Read;
+
+    let mut magic = Vec::with_capacity(TAR_MAGIC_END);
+    fs::File::open(path)?
+        .take(TAR_MAGIC_END as u64)
+        .read_to_end(&mut magic)?;
+    let compression = if magic.starts_with(&[0x1f, 0x8b]) {
+        Compression::Gz
+    } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0]) {
+        Compression::Xz
+    } else if magic.starts_with(b"BZh") {
+        Compression::Bz2
+    } else if magic.starts_with(b"PK\x03\x04") || magic.starts_with(b"PK\x05\x06") {
+        #[cfg(feature = "archive-zip")]
+        return Ok(Some(ArchiveKind::Zip));
+        #[cfg(not(feature = "archive-zip"))]
+        return Err(Error::ArchiveNotEnabled("zip".to_string()));
+    } else if is_tar(&magic) {
+        return tar_archive(None).map(Some);
+    } else {
+        return Ok(None);
+    };
+    // a compressed tar archive has the tar magic once decompressed, unknown when its
+    // compression isn't enabled
+    let mut header = Vec::with_capacity(TAR_MAGIC_END);
+    let decompressed = Extract::get_archive_reader(fs::File::open(path)?, Some(compression))
+        .ok()
+        .and_then(|reader| {
+            reader
+                .take(TAR_MAGIC_END as u64)
+                .read_to_end(&mut header)
+                .ok()
+        });
+    if decompressed.is_some() && is_tar(&header) {
+        tar_archive(Some(compression)).map(Some)
+    } else {
+        Ok(Some(ArchiveKind::Plain(Some(compression))))
+    }
+}
+
+// End of the `ustar` magic in a tar header
+const TAR_MAGIC_END: usize = 262;
+
+// Check if `header` is the start of a (ustar or gnu) tar archive
+fn is_tar(header: &[u8]) -> bool {
+    header.get(257..TAR_MAGIC_END) == Some(&b"ustar"[..])
+}
+
+// `ArchiveKind::Tar` with `compression`, if the `archive-tar` feature is enabled
+fn tar_archive(compression: Option<Compression>) -> Result<ArchiveKind> {
+    #[cfg(feature = "archive-tar")]
+    {
+        Ok(ArchiveKind::Tar(compression))
+    }
+    #[cfg(not(feature = "archive-tar"))]
+    {
+        let _ = compression;
+        Err(Error::ArchiveNotEnabled("tar".to_string()))
+    }
+}
+
 /// Extract contents of an encoded archive (e.g. tar.gz) file to a specified directory
 ///
 /// * Errors:
@@ -321,10 +557,8 @@ pub struct Extract<'a> {
     source: &'a path::Path,
     archive: Option<ArchiveKind>,
 }
-#[cfg(feature = "compression-flate2")]
-pub type GetArchiveReaderResult = Either<fs::File, flate2::read::GzDecoder<fs::File>>;
-#[cfg(not(feature = "compression-flate2"))]
-pub type GetArchiveReaderResult = fs::File;
+/// Reader of the decompressed source
+pub type GetArchiveReaderResult = Box<dyn io::Read>;
 
 impl<'a> Extract<'a> {
     /// Create an `Extract`or from a source path
@@ -336,24 +570,41 @@ impl<'a> Extract<'a> {
     }
 
     /// Specify an archive format of the source being extracted. If not specified, the
-    /// archive format will determined from the file extension.
+    /// archive format is determined from the source's magic bytes (gzip, xz, bzip2, zip
+    /// or tar), or from its file extension when they aren't recognized.
     pub fn archive(&mut self, kind: ArchiveKind) -> &mut Self {
         self.archive = Some(kind);
         self
     }
 
-    #[allow(unused_variables)]
     fn get_archive_reader(
         source: fs::File,
         compression: Option<Compression>,
-    ) -> GetArchiveReaderResult {
-        #[cfg(feature = "compression-flate2")]
-        match compression {
-            Some(Compression::Gz) => Either::Right(flate2::read::GzDecoder::new(source)),
-            None => Either::Left(source),
-        }
-        #[cfg(not(feature = "compression-flate2"))]
-        source
+    ) -> Result<GetArchiveReaderResult> {
+        Ok(match compression {
+            None => Box::new(source),
+            #[cfg(feature = "compression-flate2")]
+            Some(Compression::Gz) => Box::new(flate2::read::GzDecoder::new(source)),
+            #[cfg(not(feature = "compression-flate2"))]
+            Some(Compression::Gz) => bail!(
+                Error::Config,
+                "Extracting gzip requires the `compression-flate2` feature"
+            ),
+            #[cfg(feature = "compression-xz")]
+            Some(Compression::Xz) => Box::new(xz2::read::XzDecoder::new(source)),
+            #[cfg(not(feature = "compression-xz"))]
+            Some(Compression::Xz) => bail!(
+                Error::Config,
+                "Extracting xz requires the `compression-xz` feature"
+            ),
+            #[cfg(feature = "compression-bzip2")]
+            Some(Compression::Bz2) => Box::new(bzip2::read::BzDecoder::new(source)),
+            #[cfg(not(feature = "compression-bzip2"))]
+            Some(Compression::Bz2) => bail!(
+                Error::Config,
+                "Extracting bzip2 requires the `compression-bzip2` feature"
+            ),
+        })
     }
 
     /// Extract an entire source archive into a specified path. If the source is a single compressed
@@ -363,13 +614,13 @@ impl<'a> Extract<'a> {
         let source = fs::File::open(self.source)?;
         let archive = match self.archive {
             Some(archive) => archive,
-            None => detect_archive(&self.source)?,
+            None => detect_source_archive(self.source)?,
         };
 
         // We cannot use a feature flag in a match arm. To bypass this the code block is
         // isolated in a closure and called accordingly.
         let extract_into_plain_or_tar = |source: fs::File, compression: Option<Compression>| {
-            let mut reader = Self::get_archive_reader(source, compression);
+            let mut reader = Self::get_archive_reader(source, compression)?;
 
             match archive {
                 ArchiveKind::Plain(_) => {
@@ -418,33 +669,29 @@ impl<'a> Extract<'a> {
                 let mut archive = zip::ZipArchive::new(source)?;
                 for i in 0..archive.len() {
                     let mut file = archive.by_index(i)?;
-                    let path = into_dir.join(file.name());
-                    let mut output = fs::File::create(path)?;
-                    io::copy(&mut file, &mut output)?;
+                    unpack_zip_entry(&mut file, into_dir)?;
                 }
             }
         };
         Ok(())
     }
 
+    /// Extract an entire source archive into `dir`, preserving the archive's directory layout.
+    /// Plain (non-archive) sources are handled the same way as `extract_into`.
     pub fn extract_dir(&self, dir: &path::Path) -> Result<()> {
-        let source = fs::File::open(self.source)?;
         let archive = match self.archive {
             Some(archive) => archive,
-            None => detect_archive(&self.source)?,
+            None => detect_source_archive(self.source)?,
         };
         match archive {
-            #[cfg(feature = "archive-tar")]
-            ArchiveKind::Plain(compression) | ArchiveKind::Tar(compression) => {
-                extract_into_plain_or_tar(source, compression)?;
-            }
-            #[cfg(not(feature = "archive-tar"))]
-            ArchiveKind::Plain(compression) => {}
             #[cfg(feature = "archive-zip")]
             ArchiveKind::Zip => {
+                let source = fs::File::open(self.source)?;
                 let mut archive = zip::ZipArchive::new(source)?;
                 archive.extract(dir)?;
             }
+            #[allow(unreachable_patterns)]
+            _ => self.extract_into(dir)?,
         };
         Ok(())
     }
@@ -461,7 +708,7 @@ impl<'a> Extract<'a> {
         let source = fs::File::open(self.source)?;
         let archive = match self.archive {
             Some(archive) => archive,
-            None => detect_archive(&self.source)?,
+            None => detect_source_archive(self.source)?,
         };
 
         debug!(
@@ -472,7 +719,7 @@ impl<'a> Extract<'a> {
         // We cannot use a feature flag in a match arm. To bypass this the code block is
         // isolated in a closure and called accordingly.
         let extract_file_plain_or_tar = |source: fs::File, compression: Option<Compression>| {
-            let mut reader = Self::get_archive_reader(source, compression);
+            let mut reader = Self::get_archive_reader(source, compression)?;
 
             match archive {
                 ArchiveKind::Plain(_) => {
@@ -497,21 +744,7 @@ impl<'a> Extract<'a> {
                     debug!("Extracting from tar");
 
                     let mut archive = tar::Archive::new(reader);
-                    let mut entry = archive
-                        .entries()?
-                        .filter_map(|e| e.ok())
-                        .find(|e| {
-                            let p = e.path();
-                            debug!("Archive path: {:?}", p);
-                            p.ok().filter(|p| p == file_to_extract).is_some()
-                        })
-                        .ok_or_else(|| {
-                            Error::Update(format!(
-                                "Could not find the required path in the archive: {:?}",
-                                file_to_extract
-                            ))
-                        })?;
-                    entry.unpack_in(into_dir)?;
+                    unpack_tar_file(&mut archive, into_dir, file_to_extract, Error::Io)?;
                 }
                 #[allow(unreachable_patterns)]
                 _ => unreachable!(
@@ -535,14 +768,98 @@ impl<'a> Extract<'a> {
             ArchiveKind::Zip => {
                 let mut archive = zip::ZipArchive::new(source)?;
                 let mut file = archive.by_name(file_to_extract.to_str().unwrap())?;
-                let mut output = fs::File::create(into_dir.join(file.name()))?;
-                io::copy(&mut file, &mut output)?;
+                unpack_zip_entry(&mut file, into_dir)?;
             }
         };
         Ok(())
     }
 }
 
+// Path of an archive entry without `.` components, e.g. `bin/app` for `./bin/app`
+#[cfg(feature = "archive-tar")]
+fn normalize_archive_path(path: &path::Path) -> path::PathBuf {
+    path.components()
+        .filter(|c| *c != path::Component::CurDir)
+        .collect()
+}
+
+// Find `file_to_extract` in the tar `archive`, ignoring `.` path components, and unpack it at
+// its path inside `into_dir`. Links and entries with a path leading outside `into_dir` are
+// refused. Reading the archive fails with `read_error`.
+#[cfg(feature = "archive-tar")]
+fn unpack_tar_file<R: io::Read>(
+    archive: &mut tar::Archive<R>,
+    into_dir: &path::Path,
+    file_to_extract: &path::Path,
+    read_error: impl Fn(io::Error) -> Error,
+) -> Result<()> {
+    let file_to_extract = normalize_archive_path(file_to_extract);
+    let mut entry = archive
+        .entries()
+        .map_err(&read_error)?
+        .filter_map(|e| e.ok())
+        .find(|e| {
+            let p = e.path();
+            debug!("Archive path: {:?}", p);
+            p.ok()
+                .filter(|p| normalize_archive_path(p) == file_to_extract)
+                .is_some()
+        })
+        .ok_or_else(|| {
+            Error::Update(format!(
+                "Could not find the required path in the archive: {:?}",
+                file_to_extract
+            ))
+        })?;
+    if entry.header().entry_type().is_symlink() || entry.header().entry_type().is_hard_link() {
+        bail!(
+            Error::Update,
+            "Refusing to extract {:?}, it's a link in the archive",
+            file_to_extract
+        )
+    }
+    fs::create_dir_all(into_dir)?;
+    if !entry.unpack_in(into_dir).map_err(&read_error)? {
+        bail!(
+            Error::Update,
+            "Refusing to extract {:?}, its path leads outside of the target dir",
+            file_to_extract
+        )
+    }
+    Ok(())
+}
+
+// Unpack the zip entry `file` at its path inside `into_dir`, creating its parent dirs and
+// preserving its unix permissions. Entries with a path leading outside `into_dir` are refused.
+#[cfg(feature = "archive-zip")]
+fn unpack_zip_entry(file: &mut zip::read::ZipFile, into_dir: &path::Path) -> Result<()> {
+    let out_path = match file.enclosed_name() {
+        Some(name) => into_dir.join(name),
+        None => bail!(
+            Error::Update,
+            "Refusing to extract {:?}, its path leads outside of the target dir",
+            file.name()
+        ),
+    };
+    if file.is_dir() {
+        fs::create_dir_all(&out_path)?;
+        return Ok(());
+    }
+    if let Some(parent) = out_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut output = fs::File::create(&out_path)?;
+    io::copy(file, &mut output)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Some(mode) = file.unix_mode() {
+            fs::set_permissions(&out_path, fs::Permissions::from_mode(mode))?;
+        }
+    }
+    Ok(())
+}
+
 /// Moves a file from the given path to the specified destination.
 ///
 /// `source` and `dest` must be on the same filesystem.
@@ -583,20 +900,16 @@ impl<'a> Move<'a> {
             fs_extra::dir::move_dir(self.temp.unwrap(), dest, &options).unwrap_or(0);
         } else {
             match self.temp {
-                None => {
-                    fs::rename(self.source, dest)?;
-                }
-                Some(temp) => {
-                    if dest.exists() {
-                        fs::rename(dest, temp)?;
-                        if let Err(e) = fs::rename(self.source, dest) {
-                            fs::rename(temp, dest)?;
-                            return Err(Error::from(e));
-                        }
-                    } else {
-                        fs::rename(self.source, dest)?;
+                Some(temp) if dest.exists() => {
+                    fs::rename(dest, temp)?;
+                    if let Err(e) = fs::rename(self.source, dest) {
+                        fs::rename(temp, dest)?;
+                        return Err(Error::from(e));
                     }
                 }
+                _ => {
+                    fs::rename(self.source, dest)?;
+                }
             };
         }
 
@@ -610,35 +923,107 @@ impl<'a> Move<'a> {
 #[derive(Debug)]
 pub struct Download {
     show_progress: bool,
+    force_progress: bool,
     url: String,
     headers: reqwest::header::HeaderMap,
     progress_style: ProgressStyle,
+    client: Option<reqwest::blocking::Client>,
+    verify_header_digests: bool,
+    reporter: Option<progress::SharedReporter>,
+    cancel: Option<Arc<AtomicBool>>,
+    free_space: Option<(Vec<path::PathBuf>, u64)>,
+    pinned_cert: Option<String>,
+    stats: Mutex<Option<DownloadStats>>,
 }
 impl Download {
     /// Specify download url
     pub fn from_url(url: &str) -> Self {
         Self {
             show_progress: false,
+            force_progress: false,
             url: url.to_owned(),
             headers: reqwest::header::HeaderMap::new(),
             progress_style: ProgressStyle::default_bar()
                 .template("[{elapsed_precise}] [{bar:40}] {bytes}/{total_bytes} ({eta}) {msg}")
                 .progress_chars("=>-"),
+            client: None,
+            verify_header_digests: true,
+            reporter: None,
+            cancel: None,
+            free_space: None,
+            pinned_cert: None,
+            stats: Mutex::new(None),
         }
     }
 
-    /// Toggle download progress bar
+    /// Toggle download progress bar. When stderr isn't a terminal, e.g. in CI or piped into
+    /// a file, the downloaded byte counts are logged instead, unless `force_progress` is set
     pub fn show_progress(&mut self, b: bool) -> &mut Self {
         self.show_progress = b;
         self
     }
 
+    /// Draw the progress bar of `show_progress` even when stderr isn't a terminal
+    pub fn force_progress(&mut self, b: bool) -> &mut Self {
+        self.force_progress = b;
+        self
+    }
+
     /// Set the progress style
     pub fn set_progress_style(&mut self, progress_style: ProgressStyle) -> &mut Self {
         self.progress_style = progress_style;
         self
     }
 
+    /// Report the progress to `reporter` instead of the progress bar, regardless of
+    /// `show_progress`
+    pub fn set_progress_reporter(&mut self, reporter: progress::SharedReporter) -> &mut Self {
+        self.reporter = Some(reporter);
+        self
+    }
+
+    /// Abort the download with `Error::Cancelled` once `cancel` is set, checked before
+    /// each chunk of the response is read
+    pub fn set_cancel_flag(&mut self, cancel: Arc<AtomicBool>) -> &mut Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// Fail with `Error::InsufficientSpace` before the response body is read when its
+    /// `Content-Length` plus `margin` bytes exceeds the free space of any of `dirs`.
+    /// Skipped when the size of the download or the free space is unknown.
+    pub fn require_free_space(&mut self, dirs: Vec<path::PathBuf>, margin: u64) -> &mut Self {
+        self.free_space = Some((dirs, margin));
+        self
+    }
+
+    /// Fail with `Error::Tls`, before the request is sent, when the server's leaf certificate
+    /// doesn't have the SHA-256 `fingerprint`, hex encoded. It's checked during the TLS
+    /// handshake by a client the download builds itself, so it can't be combined with
+    /// `set_client` or `download_to_async` (`Error::Config`). Requires the `rustls-tls` feature.
+    pub fn set_pinned_cert_sha256(&mut self, fingerprint: &str) -> &mut Self {
+        self.pinned_cert = Some(fingerprint.to_owned());
+        self
+    }
+
+    /// Return the stats of the last completed download, `None` until one completed
+    pub fn stats(&self) -> Option<DownloadStats> {
+        *self.stats.lock().unwrap()
+    }
+
+    /// Set the http client used for the download, defaults to a newly built client
+    pub fn set_client(&mut self, client: reqwest::blocking::Client) -> &mut Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Toggle verifying the downloaded bytes against the `Content-MD5` and `Digest`
+    /// response headers, when present. Defaults to `true`.
+    pub fn verify_header_digests(&mut self, verify: bool) -> &mut Self {
+        self.verify_header_digests = verify;
+        self
+    }
+
     /// Set the download request headers, replaces the existing `HeaderMap`
     pub fn set_headers(&mut self, headers: reqwest::header::HeaderMap) -> &mut Self {
         self.headers = headers;
@@ -665,8 +1050,208 @@ impl Download {
     ///     * Progress-bar errors
     ///     * Reading from response to `BufReader`-buffer
     ///     * Writing from `BufReader`-buffer to `File`
+    ///     * Downloaded bytes not matching the `Content-MD5`/`Digest` response headers
+    ///     * Download - fewer or more bytes received than the `Content-Length` header says
+    ///     * Cancelled - the cancel flag was set
     pub fn download_to<T: io::Write>(&self, mut dest: T) -> Result<()> {
-        use io::BufRead;
+        let mut stream = self.open()?;
+        self.copy(&mut stream, &mut dest)?;
+        self.finish(stream)
+    }
+
+    /// Download the file behind the given `url` into the partial file `part`, resuming
+    /// from the end of an earlier, interrupted download of it when there's one.
+    /// The `ETag` (or `Last-Modified`) of the response that started `part` is kept next
+    /// to it, and sent as `If-Range` with the `Range` header of the rest of the content.
+    /// The whole content is downloaded again when the server doesn't support range
+    /// requests, the file changed since, the returned range doesn't start at the end of
+    /// `part` or the response that started `part` had no validator.
+    /// `part` is kept when the download fails, so the next attempt can resume it.
+    /// Digest headers are only checked when the whole content is downloaded.
+    ///
+    /// * Errors:
+    ///     * Same as `download_to`
+    ///     * Io - opening `part`, reading or writing its validator
+    pub fn download_resumable(&self, part: &path::Path) -> Result<()> {
+        let validator_path = resume_validator_path(part);
+        let offset = fs::metadata(part).map(|m| m.len()).unwrap_or(0);
+        let validator = if offset > 0 {
+            fs::read_to_string(&validator_path).ok()
+        } else {
+            None
+        };
+        let resp = match validator {
+            Some(ref validator) => {
+                let resp = self.send(Some((offset, validator)))?;
+                let status = resp.status();
+                if status == reqwest::StatusCode::PARTIAL_CONTENT
+                    && content_range_start(resp.headers()) == Some(offset)
+                {
+                    debug!("Resuming download of {} from byte {}", self.url, offset);
+                    let headers = resp.headers().clone();
+                    let mut stream = self.stream(status, &headers, resp, offset)?;
+                    let mut dest = fs::OpenOptions::new().append(true).open(part)?;
+                    self.copy(&mut stream, &mut dest)?;
+                    self.finish(stream)?;
+                    let _ = fs::remove_file(&validator_path);
+                    return Ok(());
+                }
+                match status {
+                    reqwest::StatusCode::PARTIAL_CONTENT
+                    | reqwest::StatusCode::RANGE_NOT_SATISFIABLE => {
+                        debug!("Can't resume download of {}, restarting", self.url);
+                        self.send(None)?
+                    }
+                    _ => {
+                        debug!(
+                            "Range requests unsupported or content changed for {}, restarting",
+                            self.url
+                        );
+                        resp
+                    }
+                }
+            }
+            None => {
+                if offset > 0 {
+                    debug!(
+                        "No validator to resume download of {}, restarting",
+                        self.url
+                    );
+                }
+                self.send(None)?
+            }
+        };
+        let (status, headers) = (resp.status(), resp.headers().clone());
+        let mut stream = self.stream(status, &headers, resp, 0)?;
+        match resume_validator(&headers) {
+            Some(validator) => fs::write(&validator_path, validator)?,
+            None => {
+                let _ = fs::remove_file(&validator_path);
+            }
+        }
+        let mut dest = fs::File::create(part)?;
+        self.copy(&mut stream, &mut dest)?;
+        self.finish(stream)?;
+        let _ = fs::remove_file(&validator_path);
+        Ok(())
+    }
+
+    /// Download a `.tar.gz` archive behind the given `url`, extracting `file_to_extract`
+    /// into `into_dir` while the response streams in, without storing the archive.
+    /// The whole response is read, so it's still verified against its digest headers.
+    ///
+    /// * Errors:
+    ///     * Same as `download_to`
+    ///     * Io - gzip decoding
+    ///     * Io - archive unpacking
+    ///     * `file_to_extract` missing from the archive, a link or leading outside `into_dir`
+    #[cfg(all(feature = "archive-tar", feature = "compression-flate2"))]
+    pub fn download_extract_file<T: AsRef<path::Path>>(
+        &self,
+        into_dir: &path::Path,
+        file_to_extract: T,
+    ) -> Result<()> {
+        let file_to_extract = file_to_extract.as_ref();
+        let mut stream = self.open()?;
+        {
+            let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(&mut stream));
+            unpack_tar_file(&mut archive, into_dir, file_to_extract, |e| {
+                self.read_error(e)
+            })?;
+        }
+        // drain the rest of the response, the digests cover the whole body
+        self.copy(&mut stream, &mut io::sink())?;
+        self.finish(stream)
+    }
+
+    /// Same as `download_to`, using the async `client`. The progress bar is updated
+    /// as the response's chunks are polled.
+    #[cfg(feature = "async")]
+    pub async fn download_to_async<T: io::Write>(
+        &self,
+        client: &reqwest::Client,
+        mut dest: T,
+    ) -> Result<()> {
+        if self.pinned_cert.is_some() {
+            bail!(
+                Error::Config,
+                "A pinned certificate can't be enforced by the given client"
+            )
+        }
+        let resp = client
+            .get(&self.url)
+            .headers(self.request_headers())
+            .send()
+            .await
+            .map_err(|e| errors::request_error(e, &self.url))?;
+        let (status, headers) = (resp.status(), resp.headers().clone());
+        let mut stream = self.stream(status, &headers, resp, 0)?;
+        while let Some(chunk) = stream.inner.chunk().await? {
+            if stream.cancelled() {
+                return Err(Error::Cancelled);
+            }
+            stream.record(&chunk);
+            dest.write_all(&chunk)?;
+        }
+        self.finish(stream)
+    }
+
+    // Finish the fully read `stream`, keeping its stats once it's verified
+    fn finish<R>(&self, stream: DownloadStream<R>) -> Result<()> {
+        let stats = stream.finish()?;
+        *self.stats.lock().unwrap() = Some(stats);
+        Ok(())
+    }
+
+    // Copy the rest of `stream` into `dest`
+    fn copy<R: io::Read>(
+        &self,
+        stream: &mut DownloadStream<R>,
+        dest: &mut dyn io::Write,
+    ) -> Result<()> {
+        io::copy(stream, dest).map_err(|e| self.read_error(e))?;
+        Ok(())
+    }
+
+    // Surface a failure while reading the response as `Error::Cancelled` when it was cancelled
+    fn read_error(&self, e: io::Error) -> Error {
+        match self.cancel {
+            Some(ref cancel) if cancel.load(Ordering::SeqCst) => Error::Cancelled,
+            _ => Error::Io(e),
+        }
+    }
+
+    // Check there's room for `size` more bytes, plus the margin, in the free space dirs
+    fn check_free_space(&self, size: u64) -> Result<()> {
+        let (dirs, margin) = match self.free_space {
+            Some((ref dirs, margin)) if size > 0 => (dirs, margin),
+            _ => return Ok(()),
+        };
+        for dir in dirs {
+            let available = match available_space(dir) {
+                Some(available) => available,
+                None => {
+                    debug!("Free space of {:?} unknown, not checked", dir);
+                    continue;
+                }
+            };
+            if size.saturating_add(margin) > available {
+                bail!(
+                    Error::InsufficientSpace,
+                    "{} bytes needed in {:?} ({} bytes download + {} bytes margin), {} bytes available",
+                    size.saturating_add(margin),
+                    dir,
+                    size,
+                    margin,
+                    available
+                )
+            }
+        }
+        Ok(())
+    }
+
+    // Request headers, with a default user agent
+    fn request_headers(&self) -> reqwest::header::HeaderMap {
         let mut headers = self.headers.clone();
         if !headers.contains_key(header::USER_AGENT) {
             headers.insert(
@@ -676,14 +1261,59 @@ impl Download {
                     .expect("invalid user-agent"),
             );
         }
+        headers
+    }
+
+    // Send the download request, returning the response body as a `DownloadStream`
+    fn open(&self) -> Result<DownloadStream<reqwest::blocking::Response>> {
+        let resp = self.send(None)?;
+        let (status, headers) = (resp.status(), resp.headers().clone());
+        self.stream(status, &headers, resp, 0)
+    }
 
-        set_ssl_vars!();
-        let resp = reqwest::blocking::Client::new()
+    // Send the download request. With a `range` of `(offset, validator)`, for the content
+    // from byte `offset` if the file still matches `validator`, the whole content otherwise.
+    fn send(&self, range: Option<(u64, &str)>) -> Result<reqwest::blocking::Response> {
+        let client = match (&self.client, &self.pinned_cert) {
+            (Some(_), Some(_)) => bail!(
+                Error::Config,
+                "A pinned certificate can't be enforced by a client set with `set_client`"
+            ),
+            (Some(client), None) => client.clone(),
+            (None, pinned) => client::ClientOptions {
+                pinned_cert_sha256: pinned.clone(),
+                ..Default::default()
+            }
+            .build_new_client()?,
+        };
+        let mut headers = self.request_headers();
+        if let Some((offset, validator)) = range {
+            headers.insert(
+                header::RANGE,
+                format!("bytes={}-", offset).parse().expect("invalid range"),
+            );
+            if let Ok(validator) = validator.parse() {
+                headers.insert(header::IF_RANGE, validator);
+            }
+        }
+        let resp = client
             .get(&self.url)
             .headers(headers)
-            .send()?;
-        let size = resp
-            .headers()
+            .send()
+            .map_err(|e| errors::request_error(e, &self.url))?;
+        Ok(resp)
+    }
+
+    // Wrap the body `inner` of a response with `status` and `headers` in a `DownloadStream`.
+    // The body is the content from byte `offset`, when resuming a download.
+    fn stream<R>(
+        &self,
+        status: reqwest::StatusCode,
+        headers: &reqwest::header::HeaderMap,
+        inner: R,
+        offset: u64,
+    ) -> Result<DownloadStream<R>> {
+        let size = headers
             .get(reqwest::header::CONTENT_LENGTH)
             .map(|val| {
                 val.to_str()
@@ -691,45 +1321,211 @@ impl Download {
                     .unwrap_or(0)
             })
             .unwrap_or(0);
-        if !resp.status().is_success() {
-            bail!(
-                Error::Update,
-                "Download request failed with status: {:?}",
-                resp.status()
-            )
+        if !status.is_success() {
+            return Err(Error::Status(status, self.url.clone()));
         }
-        let show_progress = if size == 0 { false } else { self.show_progress };
-
-        let mut src = io::BufReader::new(resp);
-        let mut downloaded = 0;
-        let mut bar = if show_progress {
-            let pb = ProgressBar::new(size);
-            pb.set_style(self.progress_style.clone());
-
-            Some(pb)
+        self.check_free_space(size)?;
+        // digest headers cover the whole content, not the range of a resumed download
+        let verifier = if self.verify_header_digests && offset == 0 {
+            let digests = checksum::header_digests(headers);
+            if digests.is_empty() {
+                None
+            } else {
+                Some(checksum::DigestVerifier::new(digests))
+            }
         } else {
             None
         };
-        loop {
-            let n = {
-                let buf = src.fill_buf()?;
-                dest.write_all(&buf)?;
-                buf.len()
-            };
-            if n == 0 {
-                break;
+        let reporter = match self.reporter {
+            Some(ref reporter) => Some(reporter.clone()),
+            None if self.show_progress
+                && (self.force_progress || io::IsTerminal::is_terminal(&io::stderr())) =>
+            {
+                Some(progress::SharedReporter::new(Box::new(
+                    progress::BarReporter::new(self.progress_style.clone()),
+                )))
             }
-            src.consume(n);
-            downloaded = min(downloaded + n as u64, size);
-
-            if let Some(ref mut bar) = bar {
-                bar.set_position(downloaded);
+            None if self.show_progress => Some(progress::SharedReporter::new(Box::new(
+                progress::LogReporter::new(),
+            ))),
+            None => None,
+        };
+        let size = if size == 0 { None } else { Some(offset + size) };
+        if let Some(ref reporter) = reporter {
+            reporter.on_start(size);
+            if offset > 0 {
+                reporter.on_progress(offset);
             }
         }
-        if let Some(ref mut bar) = bar {
-            bar.finish_with_message("Done");
+        Ok(DownloadStream {
+            inner,
+            downloaded: offset,
+            offset,
+            started: Instant::now(),
+            size,
+            reporter,
+            verifier,
+            cancel: self.cancel.clone(),
+        })
+    }
+}
+
+/// Path of the file keeping the validator of the partial download `part`, see
+/// `Download::download_resumable`
+pub(crate) fn resume_validator_path(part: &path::Path) -> path::PathBuf {
+    let mut name = part.file_name().unwrap_or_default().to_os_string();
+    name.push(".validator");
+    part.with_file_name(name)
+}
+
+// The validator to resume a download with: its strong `ETag`, else its `Last-Modified` date.
+// Weak etags can't be used with `If-Range`.
+fn resume_validator(headers: &reqwest::header::HeaderMap) -> Option<String> {
+    let value = |name| {
+        headers
+            .get(name)
+            .and_then(|v: &header::HeaderValue| v.to_str().ok())
+    };
+    match value(header::ETAG) {
+        Some(etag) if !etag.starts_with("W/") => Some(etag.to_owned()),
+        _ => value(header::LAST_MODIFIED).map(str::to_owned),
+    }
+}
+
+// First byte of a `Content-Range: bytes <first>-<last>/<size>` header
+fn content_range_start(headers: &reqwest::header::HeaderMap) -> Option<u64> {
+    let range = headers.get(header::CONTENT_RANGE)?.to_str().ok()?;
+    let (first, _) = range.trim().strip_prefix("bytes ")?.split_once('-')?;
+    first.trim().parse().ok()
+}
+
+/// Free space in bytes available to the process on the filesystem holding `path`,
+/// `None` when it can't be determined
+#[cfg(unix)]
+pub(crate) fn available_space(path: &path::Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    // the field types differ between platforms
+    #[allow(clippy::unnecessary_cast)]
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/// Free space in bytes available to the process on the filesystem holding `path`,
+/// `None` when it can't be determined
+#[cfg(windows)]
+pub(crate) fn available_space(path: &path::Path) -> Option<u64> {
+    use std::os::windows::ffi::OsStrExt;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetDiskFreeSpaceExW(
+            dir: *const u16,
+            free_to_caller: *mut u64,
+            total: *mut u64,
+            total_free: *mut u64,
+        ) -> i32;
+    }
+
+    let wide = path
+        .as_os_str()
+        .encode_wide()
+        .chain(Some(0))
+        .collect::<Vec<u16>>();
+    let mut available = 0;
+    let ok = unsafe {
+        GetDiskFreeSpaceExW(
+            wide.as_ptr(),
+            &mut available,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+    if ok == 0 {
+        None
+    } else {
+        Some(available)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn available_space(_path: &path::Path) -> Option<u64> {
+    None
+}
+
+// Download response body, updating the progress reporter and digest verifier as it's read
+struct DownloadStream<R> {
+    inner: R,
+    downloaded: u64,
+    // bytes of a resumed partial download, received earlier
+    offset: u64,
+    started: Instant,
+    size: Option<u64>,
+    reporter: Option<progress::SharedReporter>,
+    verifier: Option<checksum::DigestVerifier>,
+    cancel: Option<Arc<AtomicBool>>,
+}
+
+impl<R> DownloadStream<R> {
+    // Whether the download was cancelled through its cancel flag
+    fn cancelled(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .is_some_and(|cancel| cancel.load(Ordering::SeqCst))
+    }
+
+    // Account for the next chunk of the body
+    fn record(&mut self, chunk: &[u8]) {
+        if let Some(ref mut verifier) = self.verifier {
+            verifier.update(chunk);
+        }
+        if chunk.is_empty() {
+            return;
+        }
+        self.downloaded += chunk.len() as u64;
+        if let Some(ref reporter) = self.reporter {
+            reporter.on_progress(self.downloaded);
         }
-        Ok(())
+    }
+
+    // Finish the progress report and check the size and digests, once the body is fully
+    // read, returning the stats of the download
+    fn finish(self) -> Result<DownloadStats> {
+        if let Some(ref reporter) = self.reporter {
+            reporter.on_finish();
+        }
+        match self.size {
+            Some(size) if self.downloaded != size => bail!(
+                Error::Download,
+                "Download size mismatch: received {} of {} bytes",
+                self.downloaded,
+                size
+            ),
+            Some(_) => (),
+            None => debug!("No content-length, the download size isn't checked"),
+        }
+        if let Some(verifier) = self.verifier {
+            verifier.verify()?;
+        }
+        Ok(DownloadStats::new(
+            self.downloaded - self.offset,
+            self.started.elapsed(),
+        ))
+    }
+}
+
+impl<R: io::Read> io::Read for DownloadStream<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.cancelled() {
+            return Err(io::Error::other("download cancelled"));
+        }
+        let n = self.inner.read(buf)?;
+        self.record(&buf[..n]);
+        Ok(n)
     }
 }
 
@@ -745,6 +1541,306 @@ mod tests {
         path::{Path, PathBuf},
     };
 
+    #[test]
+    fn status_helpers() {
+        let status = Status::UpToDate("1.2.0".into());
+        assert!(status.uptodate() && !status.updated());
+        assert_eq!(status.version(), "1.2.0");
+
+        assert!(status.download_stats().is_none());
+
+        let stats = DownloadStats::new(3000, Duration::from_secs(2));
+        assert_eq!(stats.avg_bps, 1500);
+        let stats = stats.combine(DownloadStats::new(1000, Duration::from_secs(2)));
+        assert_eq!((stats.bytes, stats.avg_bps), (4000, 1000));
+        let status = Status::Updated("1.3.0".into(), Some(stats), vec![]);
+        assert!(status.updated() && !status.uptodate());
+        assert_eq!(status.version(), "1.3.0");
+        assert_eq!(status.download_stats(), Some(&stats));
+    }
+
+    #[test]
+    fn report_download_progress() {
+        type Events = std::sync::Arc<std::sync::Mutex<Vec<String>>>;
+        struct Recorder(Events);
+        impl ProgressReporter for Recorder {
+            fn on_start(&self, total: Option<u64>) {
+                self.0.lock().unwrap().push(format!("start {:?}", total));
+            }
+            fn on_progress(&self, downloaded: u64) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("progress {}", downloaded));
+            }
+            fn on_finish(&self) {
+                self.0.lock().unwrap().push("finish".into());
+            }
+        }
+
+        let events = Events::default();
+        let mut download = Download::from_url("http://host/app");
+        download.set_progress_reporter(progress::SharedReporter::new(Box::new(Recorder(
+            events.clone(),
+        ))));
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(reqwest::header::CONTENT_LENGTH, "6".parse().unwrap());
+        let body = io::Cursor::new(b"abcdef".to_vec());
+        let mut stream = download
+            .stream(reqwest::StatusCode::OK, &headers, body, 0)
+            .unwrap();
+        let mut buf = [0; 4];
+        while stream.read(&mut buf).unwrap() > 0 {}
+        assert_eq!(stream.finish().unwrap().bytes, 6);
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec!["start Some(6)", "progress 4", "progress 6", "finish"]
+        );
+    }
+
+    #[test]
+    fn download_size_mismatch() {
+        let download = Download::from_url("http://host/app");
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(reqwest::header::CONTENT_LENGTH, "6".parse().unwrap());
+        let body = io::Cursor::new(b"abc".to_vec());
+        let mut stream = download
+            .stream(reqwest::StatusCode::OK, &headers, body, 0)
+            .unwrap();
+        io::copy(&mut stream, &mut io::sink()).unwrap();
+        let err = stream.finish().unwrap_err();
+        assert!(matches!(err, Error::Download(_)), "{}", err);
+
+        let body = io::Cursor::new(b"abc".to_vec());
+        let mut stream = download
+            .stream(reqwest::StatusCode::OK, &Default::default(), body, 0)
+            .unwrap();
+        io::copy(&mut stream, &mut io::sink()).unwrap();
+        stream.finish().unwrap();
+    }
+
+    #[test]
+    fn cancel_download() {
+        // cancels the download once its first chunk is read
+        struct Body(Arc<AtomicBool>, usize);
+        impl io::Read for Body {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.1 += 1;
+                self.0.store(true, Ordering::SeqCst);
+                buf[0] = b'a';
+                Ok(1)
+            }
+        }
+        let cancel = Arc::new(AtomicBool::new(false));
+        let mut download = Download::from_url("http://host/app");
+        download.set_cancel_flag(cancel.clone());
+        let mut stream = download
+            .stream(
+                reqwest::StatusCode::OK,
+                &Default::default(),
+                Body(cancel.clone(), 0),
+                0,
+            )
+            .unwrap();
+        let mut dest = Vec::new();
+        let err = download.copy(&mut stream, &mut dest).unwrap_err();
+        assert!(matches!(err, Error::Cancelled), "{}", err);
+        assert_eq!(dest, b"a");
+        assert_eq!(stream.inner.1, 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn insufficient_free_space() {
+        let dir = std::env::temp_dir();
+        let available = available_space(&dir).unwrap();
+        let mut download = Download::from_url("http://host/app");
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(reqwest::header::CONTENT_LENGTH, "3".parse().unwrap());
+
+        download.require_free_space(vec![dir.clone()], available);
+        let err = download
+            .stream(reqwest::StatusCode::OK, &headers, io::empty(), 0)
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::InsufficientSpace(_)), "{}", err);
+
+        download.require_free_space(vec![dir], 0);
+        assert!(download
+            .stream(reqwest::StatusCode::OK, &headers, io::empty(), 0)
+            .is_ok());
+    }
+
+    // Serve one request with `response`, returning the request that was received
+    fn serve_once<R: AsRef<[u8]> + Send + 'static>(
+        response: R,
+    ) -> (String, std::thread::JoinHandle<String>) {
+        let (url, handle) = serve(vec![response]);
+        (
+            url,
+            std::thread::spawn(move || handle.join().unwrap().remove(0)),
+        )
+    }
+
+    // Serve the `responses` to consecutive connections, returning the requests received
+    fn serve<R: AsRef<[u8]> + Send + 'static>(
+        responses: Vec<R>,
+    ) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/app", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            let mut requests = vec![];
+            for response in responses {
+                let (mut conn, _) = listener.accept().unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let n = conn.read(&mut buf).unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                }
+                conn.write_all(response.as_ref()).unwrap();
+                requests.push(String::from_utf8(request).unwrap().to_lowercase());
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn resume_download() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("self_update_resume_download")
+            .tempdir()
+            .expect("Failed to create temp dir");
+        let part = tmp_dir.path().join("app.part");
+        let validator = resume_validator_path(&part);
+        fs::write(&part, b"abc").unwrap();
+        fs::write(&validator, "\"v1\"").unwrap();
+
+        let (url, server) = serve_once(
+            "HTTP/1.1 206 Partial Content\r\ncontent-length: 3\r\n\
+             content-range: bytes 3-5/6\r\n\r\ndef",
+        );
+        Download::from_url(&url).download_resumable(&part).unwrap();
+        let request = server.join().unwrap();
+        assert!(request.contains("range: bytes=3-"));
+        assert!(request.contains("if-range: \"v1\""));
+        assert_eq!(fs::read(&part).unwrap(), b"abcdef");
+        assert!(!validator.exists());
+
+        // the server ignores the range, or the file changed, and sends the whole content
+        fs::write(&part, b"abc").unwrap();
+        fs::write(&validator, "\"v1\"").unwrap();
+        let (url, server) = serve_once("HTTP/1.1 200 OK\r\ncontent-length: 6\r\n\r\nABCDEF");
+        Download::from_url(&url).download_resumable(&part).unwrap();
+        server.join().unwrap();
+        assert_eq!(fs::read(&part).unwrap(), b"ABCDEF");
+
+        // a range not starting at the end of `part` restarts the download
+        fs::write(&part, b"abc").unwrap();
+        fs::write(&validator, "\"v1\"").unwrap();
+        let (url, server) = serve(vec![
+            "HTTP/1.1 206 Partial Content\r\ncontent-length: 3\r\n\
+             content-range: bytes 0-2/6\r\nconnection: close\r\n\r\nXYZ",
+            "HTTP/1.1 200 OK\r\ncontent-length: 6\r\nconnection: close\r\n\r\nABCDEF",
+        ]);
+        Download::from_url(&url).download_resumable(&part).unwrap();
+        let requests = server.join().unwrap();
+        assert!(!requests[1].contains("range:"));
+        assert_eq!(fs::read(&part).unwrap(), b"ABCDEF");
+
+        // without a validator, `part` isn't resumed
+        fs::write(&part, b"abc").unwrap();
+        let (url, server) = serve_once("HTTP/1.1 200 OK\r\ncontent-length: 6\r\n\r\nABCDEF");
+        Download::from_url(&url).download_resumable(&part).unwrap();
+        assert!(!server.join().unwrap().contains("range:"));
+        assert_eq!(fs::read(&part).unwrap(), b"ABCDEF");
+
+        // a truncated response keeps the received bytes for the next attempt
+        fs::remove_file(&part).unwrap();
+        let resp = "HTTP/1.1 200 OK\r\ncontent-length: 6\r\netag: \"v2\"\r\n\
+                    connection: close\r\n\r\nABC";
+        let (url, server) = serve_once(resp);
+        assert!(Download::from_url(&url).download_resumable(&part).is_err());
+        server.join().unwrap();
+        assert_eq!(fs::read(&part).unwrap(), b"ABC");
+        assert_eq!(fs::read_to_string(&validator).unwrap(), "\"v2\"");
+    }
+
+    #[test]
+    fn target_matches_cfg() {
+        let env = if cfg!(target_env = "musl") {
+            "musl"
+        } else if cfg!(target_env = "gnu") {
+            "gnu"
+        } else if cfg!(target_env = "msvc") {
+            "msvc"
+        } else {
+            ""
+        };
+        let targets = [
+            ("x86_64", "linux", "gnu", "x86_64-unknown-linux-gnu"),
+            ("x86_64", "linux", "musl", "x86_64-unknown-linux-musl"),
+            ("aarch64", "linux", "gnu", "aarch64-unknown-linux-gnu"),
+            ("aarch64", "linux", "musl", "aarch64-unknown-linux-musl"),
+            ("arm", "linux", "gnu", "armv7-unknown-linux-gnueabihf"),
+            ("x86_64", "macos", "", "x86_64-apple-darwin"),
+            ("aarch64", "macos", "", "aarch64-apple-darwin"),
+            ("x86_64", "windows", "msvc", "x86_64-pc-windows-msvc"),
+            ("x86", "windows", "msvc", "i686-pc-windows-msvc"),
+        ];
+        let current = (std::env::consts::ARCH, std::env::consts::OS, env);
+        let target = get_target();
+        if let Some(&(.., expected)) = targets
+            .iter()
+            .find(|&&(arch, os, env, _)| (arch, os, env) == current)
+        {
+            // other 32-bit arm targets share the arch, os and env of armv7
+            if current.0 != "arm" || target.starts_with("armv7") {
+                assert_eq!(target, expected);
+            }
+        }
+        assert!(target.contains(if current.1 == "macos" {
+            "darwin"
+        } else {
+            current.1
+        }));
+    }
+
+    #[test]
+    fn confirm_with_reader() {
+        let mut out = Vec::new();
+        confirm_with("Continue? ", &mut io::Cursor::new("y\n"), &mut out).unwrap();
+        assert_eq!(out, b"Continue? ");
+        confirm_with("Continue? ", &mut io::Cursor::new("\n"), &mut io::sink()).unwrap();
+
+        let err =
+            confirm_with("Continue? ", &mut io::Cursor::new("n\n"), &mut io::sink()).unwrap_err();
+        assert!(err.to_string().contains("Update aborted"), "{}", err);
+    }
+
+    #[test]
+    fn trim_text_bom() {
+        assert_eq!(trim_text("\u{feff}1.2.3\r\n"), "1.2.3");
+        assert_eq!(trim_text("  abc \n"), "abc");
+        assert_eq!(trim_text(""), "");
+    }
+
+    #[test]
+    fn supported_formats() {
+        let formats = supported_archive_formats();
+        assert_eq!(cfg!(feature = "archive-zip"), formats.contains(&"zip"));
+        assert_eq!(cfg!(feature = "archive-tar"), formats.contains(&"tar"));
+        assert_eq!(
+            cfg!(feature = "compression-flate2"),
+            formats.contains(&"gz")
+        );
+        assert_eq!(
+            cfg!(all(feature = "archive-tar", feature = "compression-flate2")),
+            formats.contains(&"tar.gz")
+        );
+    }
+
     #[test]
     fn detect_plain() {
         assert_eq!(
@@ -776,6 +1872,112 @@ mod tests {
         );
     }
 
+    #[cfg(not(feature = "archive-tar"))]
+    #[test]
+    #[ignore]
+    fn detect_tar_xz_bzip2() {
+        println!("WARNING: Please enable 'archive-tar' feature!");
+    }
+    #[cfg(feature = "archive-tar")]
+    #[test]
+    fn detect_tar_xz_bzip2() {
+        for (name, kind) in [
+            ("Something.tar.xz", ArchiveKind::Tar(Some(Compression::Xz))),
+            ("Something.txz", ArchiveKind::Tar(Some(Compression::Xz))),
+            (
+                "Something.exe.xz",
+                ArchiveKind::Plain(Some(Compression::Xz)),
+            ),
+            (
+                "Something.tar.bz2",
+                ArchiveKind::Tar(Some(Compression::Bz2)),
+            ),
+            ("Something.tbz2", ArchiveKind::Tar(Some(Compression::Bz2))),
+            (
+                "Something.exe.bz2",
+                ArchiveKind::Plain(Some(Compression::Bz2)),
+            ),
+        ] {
+            assert_eq!(
+                kind,
+                detect_archive(&PathBuf::from(name)).unwrap(),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[cfg(not(all(
+        feature = "archive-tar",
+        feature = "archive-zip",
+        feature = "compression-flate2"
+    )))]
+    #[test]
+    #[ignore]
+    fn detect_by_magic_bytes() {
+        println!("WARNING: Please enable 'archive-tar archive-zip compression-flate2' features!");
+    }
+    #[cfg(all(
+        feature = "archive-tar",
+        feature = "archive-zip",
+        feature = "compression-flate2"
+    ))]
+    #[test]
+    fn detect_by_magic_bytes() {
+        let tmp_dir = tempfile::tempdir().expect("tempdir fail");
+        let detect = |content: &[u8]| {
+            // extension-less, as served by `download?id=` urls
+            let fp = tmp_dir.path().join("download");
+            fs::write(&fp, content).unwrap();
+            detect_source_archive(&fp).unwrap()
+        };
+        let gzip = |data: &[u8]| {
+            let mut e = GzEncoder::new(vec![], flate2::Compression::default());
+            e.write_all(data).expect("gz encode fail");
+            e.finish().expect("gz finish fail")
+        };
+        let mut ar = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        header.set_cksum();
+        ar.append_data(&mut header, "app", &b"\x7fELF"[..]).unwrap();
+        let tar = ar.into_inner().unwrap();
+        let mut zip = zip::ZipWriter::new(io::Cursor::new(vec![]));
+        zip.start_file("app", zip::write::FileOptions::default())
+            .unwrap();
+        zip.write_all(b"\x7fELF").unwrap();
+        let zip = zip.finish().unwrap().into_inner();
+
+        assert_eq!(detect(b"\x7fELF\x02\x01\x01"), ArchiveKind::Plain(None));
+        assert_eq!(detect(b""), ArchiveKind::Plain(None));
+        assert_eq!(detect(&tar), ArchiveKind::Tar(None));
+        assert_eq!(detect(&zip), ArchiveKind::Zip);
+        assert_eq!(detect(&gzip(&tar)), ArchiveKind::Tar(Some(Compression::Gz)));
+        assert_eq!(
+            detect(&gzip(b"\x7fELF")),
+            ArchiveKind::Plain(Some(Compression::Gz))
+        );
+        assert_eq!(
+            detect(&[0xfd, b'7', b'z', b'X', b'Z', 0, 0]),
+            ArchiveKind::Plain(Some(Compression::Xz))
+        );
+        #[cfg(feature = "compression-xz")]
+        assert_eq!(detect(&xz(&tar)), ArchiveKind::Tar(Some(Compression::Xz)));
+        #[cfg(feature = "compression-bzip2")]
+        assert_eq!(
+            detect(&bzip2(&tar)),
+            ArchiveKind::Tar(Some(Compression::Bz2))
+        );
+
+        // the content wins over a misleading extension
+        let fp = tmp_dir.path().join("app.zip");
+        fs::write(&fp, gzip(&tar)).unwrap();
+        assert_eq!(
+            detect_source_archive(&fp).unwrap(),
+            ArchiveKind::Tar(Some(Compression::Gz))
+        );
+    }
+
     #[cfg(not(feature = "archive-tar"))]
     #[test]
     #[ignore]
@@ -839,7 +2041,7 @@ mod tests {
             .expect("tempdir fail");
         let out_path = out_tmp.path();
         Extract::from_source(&fp)
-            .extract_into(&out_path)
+            .extract_into(out_path)
             .expect("extract fail");
         let out_file = out_path.join("temp");
         assert!(out_file.exists());
@@ -871,7 +2073,7 @@ mod tests {
             .expect("tempdir fail");
         let out_path = out_tmp.path();
         Extract::from_source(&fp)
-            .extract_into(&out_path)
+            .extract_into(out_path)
             .expect("extract fail");
         let out_file = out_path.join("temp.txt");
         assert!(out_file.exists());
@@ -925,7 +2127,7 @@ mod tests {
             .expect("tempdir fail");
         let out_path = out_tmp.path();
         Extract::from_source(&archive_fp)
-            .extract_into(&out_path)
+            .extract_into(out_path)
             .expect("extract fail");
 
         let out_file = out_path.join("inner_archive/temp.txt");
@@ -962,13 +2164,100 @@ mod tests {
             .expect("tempdir fail");
         let out_path = out_tmp.path();
         Extract::from_source(&fp)
-            .extract_file(&out_path, "renamed_file")
+            .extract_file(out_path, "renamed_file")
             .expect("extract fail");
         let out_file = out_path.join("renamed_file");
         assert!(out_file.exists());
         cmp_content(out_file, "This is a test!");
     }
 
+    // Tar archive of `inner_archive/temp.txt`, compressed by `compress`, written to `name`
+    #[cfg(all(
+        feature = "archive-tar",
+        any(feature = "compression-xz", feature = "compression-bzip2")
+    ))]
+    fn tar_fixture(dir: &Path, name: &str, compress: fn(&[u8]) -> Vec<u8>) -> PathBuf {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(15);
+        header.set_mode(0o644);
+        header.set_cksum();
+        let mut ar = tar::Builder::new(vec![]);
+        ar.append_data(
+            &mut header,
+            "inner_archive/temp.txt",
+            &b"This is a test!"[..],
+        )
+        .expect("tar append fail");
+        let tar = ar.into_inner().expect("failed getting tar writer");
+        let archive_fp = dir.join(name);
+        fs::write(&archive_fp, compress(&tar)).expect("failed writing archive file");
+        archive_fp
+    }
+
+    #[cfg(feature = "compression-xz")]
+    fn xz(data: &[u8]) -> Vec<u8> {
+        let mut e = xz2::write::XzEncoder::new(vec![], 6);
+        e.write_all(data).expect("xz encode fail");
+        e.finish().expect("xz finish fail")
+    }
+
+    #[cfg(feature = "compression-bzip2")]
+    fn bzip2(data: &[u8]) -> Vec<u8> {
+        let mut e = bzip2::write::BzEncoder::new(vec![], bzip2::Compression::default());
+        e.write_all(data).expect("bzip2 encode fail");
+        e.finish().expect("bzip2 finish fail")
+    }
+
+    #[cfg(not(all(feature = "archive-tar", feature = "compression-xz")))]
+    #[test]
+    #[ignore]
+    fn unpack_tar_xz() {
+        println!("WARNING: Please enable 'archive-tar compression-xz' features!");
+    }
+    #[cfg(all(feature = "archive-tar", feature = "compression-xz"))]
+    #[test]
+    fn unpack_tar_xz() {
+        let tmp_dir = tempfile::tempdir().expect("tempdir fail");
+        let archive_fp = tar_fixture(tmp_dir.path(), "archive_file.tar.xz", xz);
+        let out_path = tmp_dir.path().join("out");
+        Extract::from_source(&archive_fp)
+            .extract_file(&out_path, "inner_archive/temp.txt")
+            .expect("extract fail");
+        cmp_content(out_path.join("inner_archive/temp.txt"), "This is a test!");
+
+        let fp = tmp_dir.path().join("temp.xz");
+        fs::write(&fp, xz(b"This is a plain test!")).unwrap();
+        Extract::from_source(&fp)
+            .extract_file(&out_path, "renamed_file")
+            .expect("extract fail");
+        cmp_content(out_path.join("renamed_file"), "This is a plain test!");
+    }
+
+    #[cfg(not(all(feature = "archive-tar", feature = "compression-bzip2")))]
+    #[test]
+    #[ignore]
+    fn unpack_tar_bzip2() {
+        println!("WARNING: Please enable 'archive-tar compression-bzip2' features!");
+    }
+    #[cfg(all(feature = "archive-tar", feature = "compression-bzip2"))]
+    #[test]
+    fn unpack_tar_bzip2() {
+        let tmp_dir = tempfile::tempdir().expect("tempdir fail");
+        let archive_fp = tar_fixture(tmp_dir.path(), "archive_file.tbz2", bzip2);
+        let out_path = tmp_dir.path().join("out");
+        Extract::from_source(&archive_fp)
+            .extract_into(&out_path)
+            .expect("extract fail");
+        cmp_content(out_path.join("inner_archive/temp.txt"), "This is a test!");
+
+        let fp = tmp_dir.path().join("temp.bz2");
+        fs::write(&fp, bzip2(b"This is a plain test!")).unwrap();
+        Extract::from_source(&fp)
+            .extract_file(&out_path, "renamed_file")
+            .expect("extract fail");
+        cmp_content(out_path.join("renamed_file"), "This is a plain test!");
+    }
+
     #[cfg(not(all(feature = "archive-tar", feature = "compression-flate2")))]
     #[test]
     #[ignore]
@@ -1009,7 +2298,7 @@ mod tests {
             .expect("tempdir fail");
         let out_path = out_tmp.path();
         Extract::from_source(&archive_fp)
-            .extract_file(&out_path, "inner_archive/temp.txt")
+            .extract_file(out_path, "inner_archive/temp.txt")
             .expect("extract fail");
         let out_file = out_path.join("inner_archive/temp.txt");
         assert!(out_file.exists());
@@ -1052,7 +2341,7 @@ mod tests {
             .expect("tempdir fail");
         let out_path = out_tmp.path();
         Extract::from_source(&archive_path)
-            .extract_into(&out_path)
+            .extract_into(out_path)
             .expect("extract fail");
         let out_file = out_path.join("zipped.txt");
         assert!(out_file.exists());
@@ -1099,10 +2388,118 @@ mod tests {
             .expect("tempdir fail");
         let out_path = out_tmp.path();
         Extract::from_source(&archive_path)
-            .extract_file(&out_path, "zipped2.txt")
+            .extract_file(out_path, "zipped2.txt")
             .expect("extract fail");
         let out_file = out_path.join("zipped2.txt");
         assert!(out_file.exists());
         cmp_content(&out_file, "This is a second test!");
     }
+
+    #[cfg(feature = "archive-zip")]
+    #[test]
+    fn unpack_zip_nested_file() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("self_update_unpack_zip_nested")
+            .tempdir()
+            .expect("tempdir fail");
+        let tmp_path = tmp_dir.path();
+
+        let archive_path = tmp_path.join("archive.zip");
+        let archive_file = File::create(&archive_path).expect("create file fail");
+        let mut zip = zip::ZipWriter::new(archive_file);
+        let options = zip::write::FileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored)
+            .unix_permissions(0o750);
+        zip.start_file("bin/app", options)
+            .expect("failed starting zip file");
+        zip.write_all(b"app").expect("failed writing to zip");
+        zip.start_file("../evil", options)
+            .expect("failed starting second zip file");
+        zip.write_all(b"evil").expect("failed writing to zip");
+        zip.finish().expect("failed finishing zip");
+
+        let out_path = tmp_path.join("out");
+        Extract::from_source(&archive_path)
+            .extract_file(&out_path, "bin/app")
+            .expect("extract fail");
+        let out_file = out_path.join("bin").join("app");
+        cmp_content(&out_file, "app");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&out_file).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o750);
+        }
+
+        assert!(Extract::from_source(&archive_path)
+            .extract_into(&out_path)
+            .is_err());
+        assert!(!tmp_path.join("evil").exists());
+    }
+
+    #[cfg(all(feature = "archive-tar", feature = "compression-flate2"))]
+    #[test]
+    fn unpack_tar_gzip_nested_file() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("self_update_unpack_tar_gzip_nested")
+            .tempdir()
+            .expect("tempdir fail");
+        let tmp_path = tmp_dir.path();
+
+        let mut ar = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(3);
+        header.set_mode(0o755);
+        ar.append_data(&mut header, "./bin/app", &b"app"[..])
+            .expect("tar append fail");
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        ar.append_link(&mut header, "bin/link", "/etc/passwd")
+            .expect("tar append link fail");
+        let tar_writer = ar.into_inner().expect("failed getting tar writer");
+
+        let archive_fp = tmp_path.join("app.tar.gz");
+        let archive_file = File::create(&archive_fp).expect("failed creating archive file");
+        let mut e = GzEncoder::new(archive_file, flate2::Compression::default());
+        e.write_all(&tar_writer).expect("gz write fail");
+        e.finish().expect("gz finish fail");
+
+        let out_path = tmp_path.join("out");
+        Extract::from_source(&archive_fp)
+            .extract_file(&out_path, "bin/app")
+            .expect("extract fail");
+        cmp_content(out_path.join("bin").join("app"), "app");
+
+        assert!(Extract::from_source(&archive_fp)
+            .extract_file(&out_path, "bin/link")
+            .is_err());
+        assert!(!out_path.join("bin").join("link").exists());
+
+        // same checks when extracting while downloading
+        let archive = fs::read(&archive_fp).expect("read archive fail");
+        let response = [
+            format!(
+                "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n",
+                archive.len()
+            )
+            .into_bytes(),
+            archive,
+        ]
+        .concat();
+        let streamed_path = tmp_path.join("streamed");
+        let (url, server) = serve_once(response.clone());
+        Download::from_url(&url)
+            .download_extract_file(&streamed_path, "bin/app")
+            .expect("download extract fail");
+        server.join().unwrap();
+        cmp_content(streamed_path.join("bin").join("app"), "app");
+
+        let (url, server) = serve_once(response);
+        assert!(Download::from_url(&url)
+            .download_extract_file(&streamed_path, "bin/link")
+            .is_err());
+        server.join().unwrap();
+        assert!(!streamed_path.join("bin").join("link").exists());
+    }
 }
@@ -8,10 +8,12 @@ macro_rules! cargo_crate_version {
     };
 }
 
-/// Set ssl cert env. vars to make sure openssl can find required files
+/// Set ssl cert env. vars to make sure openssl can find required files.
+/// A no-op with the `rustls-tls` backend, which doesn't read them. Skipped by the builders
+/// when a `ca_bundle` is set.
 macro_rules! set_ssl_vars {
     () => {
-        #[cfg(target_os = "linux")]
+        #[cfg(all(target_os = "linux", not(feature = "rustls-tls")))]
         {
             if ::std::env::var_os("SSL_CERT_FILE").is_none() {
                 ::std::env::set_var("SSL_CERT_FILE", "/etc/ssl/certs/ca-certificates.crt");
@@ -23,6 +25,14 @@ macro_rules! set_ssl_vars {
     };
 }
 
+/// Log an event of an update phase under the `self_update::phase` target, formatted as
+/// `key=value` pairs, e.g. `phase=download event=start version="1.2.0"`
+macro_rules! phase {
+    ($($arg:tt)+) => {
+        info!(target: "self_update::phase", $($arg)+)
+    };
+}
+
 /// Helper to `print!` and immediately `flush` `stdout`
 macro_rules! print_flush {
     ($literal:expr) => {
@@ -21,37 +21,10 @@ pub struct Setting {
     install_bin: Option<String>,
     retry_time: u32,
     ignore_ver_compare: bool,
-    before_cmd:Option<String>,
-    after_cmd:Option<String>
-}
-
-fn bin_ver(bin: &Path) -> Option<String> {
-    use std::process::*;
-    let output = if cfg!(target_os = "windows") {
-        Command::new(bin)
-            .args(&["--version"])
-            .output()
-            .expect("failed to execute process")
-    } else {
-        Command::new(bin)
-            .arg("--version")
-            .arg("")
-            .output()
-            .expect("failed to execute process")
-    };
-    use regex::Regex;
-    let re = Regex::new(r"\d+\S+").unwrap();
-    let msg = if output.status.success() {
-        String::from_utf8(output.stdout).unwrap()
-    } else {
-        String::from_utf8(output.stderr).unwrap()
-    };
-    let cap = re.captures(&msg).unwrap();
-    if cap.len() > 0 {
-        Some(cap.get(0).unwrap().as_str().into())
-    } else {
-        None
-    }
+    before_cmd: Option<String>,
+    after_cmd: Option<String>,
+    service_name: Option<String>,
+    dry_run: bool,
 }
 
 fn run() -> Result<(), Box<dyn ::std::error::Error>> {
@@ -64,23 +37,31 @@ fn run() -> Result<(), Box<dyn ::std::error::Error>> {
     let ignore_ver = setting.ignore_ver_compare;
     let bin_name = setting.install_bin.unwrap_or("CloudAgent.exe".into());
     let bin_dir = Path::new(&path);
-    if !bin_dir.is_dir() {
+    if !bin_dir.is_dir() && !setting.dry_run {
         info!("Create Dir:{:?}", &bin_dir);
-        fs::create_dir_all(&bin_dir)?;
+        fs::create_dir_all(bin_dir)?;
     }
     info!("Update Dir:{:?}", &bin_dir);
-    let bin_path = bin_dir.join(&bin_name);
-    let ver = bin_ver(&bin_path).unwrap();
-    let status = update::backends::cloud::Update::configure()
+    let mut builder = update::backends::cloud::Update::configure();
+    if let Some(ref cmd) = setting.before_cmd {
+        builder.before_cmd(cmd);
+    }
+    if let Some(ref cmd) = setting.after_cmd {
+        builder.after_cmd(cmd);
+    }
+    if let Some(ref name) = setting.service_name {
+        builder.service_name(name);
+    }
+    let status = builder
         .name("Agent")
         .custom_url(&api_root)
         .bin_name(&bin_name)
         .no_confirm(true)
         .ignore_ver_compare(ignore_ver)
+        .retries(setting.retry_time)
+        .dry_run(setting.dry_run)
         .show_download_progress(true)
-        .bin_install_path(&bin_dir)
-        .before_cmd(setting.before_cmd.as_ref().unwrap())
-        .after_cmd(setting.after_cmd.as_ref().unwrap())
+        .bin_install_path(bin_dir)
         //.target_version_tag("v9.9.10")
         //.show_output(false)
         //.no_confirm(true)
@@ -90,10 +71,19 @@ fn run() -> Result<(), Box<dyn ::std::error::Error>> {
         // you obtain it via another mechanism, such as environment variables
         // or prompting the user for input
         //.auth_token(env!("DOWNLOAD_AUTH_TOKEN"))
-        .current_version(&ver)
+        .detect_current_version(true)
         .build()?
         .update()?;
-    println!("Updated version: `{}`!", status.version());
+    if let update::Status::DryRun(ref plan) = status {
+        println!(
+            "Would update to `{}` from {:?} into {:?}",
+            plan.target_version, plan.asset_url, plan.install_path
+        );
+    } else if status.updated() {
+        println!("Updated version: `{}`!", status.version());
+    } else {
+        println!("Already up to date: `{}`", status.version());
+    }
     Ok(())
 }
 
@@ -118,7 +108,6 @@ pub fn main() -> std::io::Result<()> {
             config.clone(),
             OpenOptions::new()
                 .read(true)
-                .write(true)
                 .append(true)
                 .create(true)
                 .open(&path)
@@ -0,0 +1,143 @@
+/*! Certificate pinning
+
+Checking the leaf certificate presented by the server against a pinned SHA-256
+fingerprint, see `pinned_cert_sha256`. The fingerprint is checked during the TLS
+handshake, on top of the usual certificate validation, so nothing, credentials
+included, is sent to a server that doesn't present the pinned certificate.
+Requires the `rustls-tls` feature.
+*/
+use crate::errors::*;
+#[cfg(feature = "rustls-tls")]
+use sha2::{Digest, Sha256};
+
+/// Normalize the hex SHA-256 `fingerprint`, optionally colon separated, to lowercase hex
+pub(crate) fn fingerprint(fingerprint: &str) -> Result<String> {
+    let hex = fingerprint.trim().replace(':', "").to_lowercase();
+    if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        bail!(
+            Error::Config,
+            "Invalid certificate fingerprint {:?}, expected a hex SHA-256 digest",
+            fingerprint
+        )
+    }
+    Ok(hex)
+}
+
+// Part of the handshake error of a certificate not matching the pin, to tell it apart
+// from other TLS errors
+const MISMATCH: &str = "doesn't match the pinned fingerprint";
+
+/// TLS config of a client only completing the handshake with a server presenting the
+/// `pinned` certificate, trusting the built-in roots and the DER `extra_roots`
+#[cfg(feature = "rustls-tls")]
+pub(crate) fn tls_config(
+    pinned: &str,
+    extra_roots: &[Vec<u8>],
+    tls13_only: bool,
+) -> Result<rustls::ClientConfig> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+        rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+            anchor.subject,
+            anchor.spki,
+            anchor.name_constraints,
+        )
+    }));
+    let (_, ignored) = roots.add_parsable_certificates(extra_roots);
+    if ignored > 0 {
+        bail!(
+            Error::Config,
+            "{} invalid certificates in the CA bundle",
+            ignored
+        )
+    }
+    let versions: &[&rustls::SupportedProtocolVersion] = if tls13_only {
+        &[&rustls::version::TLS13]
+    } else {
+        &[&rustls::version::TLS12, &rustls::version::TLS13]
+    };
+    let verifier = PinnedVerifier {
+        inner: rustls::client::WebPkiVerifier::new(roots, None),
+        fingerprint: fingerprint(pinned)?,
+    };
+    Ok(rustls::ClientConfig::builder()
+        .with_safe_default_cipher_suites()
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(versions)
+        .map_err(|e| format_err!(Error::Config, "Invalid TLS versions: {}", e))?
+        .with_custom_certificate_verifier(std::sync::Arc::new(verifier))
+        .with_no_client_auth())
+}
+
+// Usual certificate validation, followed by the check of the leaf certificate's fingerprint
+#[cfg(feature = "rustls-tls")]
+struct PinnedVerifier {
+    inner: rustls::client::WebPkiVerifier,
+    fingerprint: String,
+}
+
+#[cfg(feature = "rustls-tls")]
+impl rustls::client::ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &rustls::Certificate,
+        intermediates: &[rustls::Certificate],
+        server_name: &rustls::ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: std::time::SystemTime,
+    ) -> std::result::Result<rustls::client::ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        )?;
+        let actual = format!("{:x}", Sha256::digest(&end_entity.0));
+        if actual != self.fingerprint {
+            return Err(rustls::Error::General(format!(
+                "Server certificate {} {} {}",
+                actual, MISMATCH, self.fingerprint
+            )));
+        }
+        Ok(verified)
+    }
+}
+
+/// `Error::Tls` when the request failed `e` because the server's certificate didn't
+/// match the pin
+pub(crate) fn mismatch(e: &reqwest::Error) -> Option<Error> {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(e);
+    while let Some(err) = source {
+        let msg = err.to_string();
+        if msg.contains(MISMATCH) {
+            let url = e.url().map(|url| url.as_str()).unwrap_or_default();
+            return Some(format_err!(Error::Tls, "{} - for: {:?}", msg, url));
+        }
+        source = err.source();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FINGERPRINT: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+    #[test]
+    fn pinned_fingerprint() {
+        let colons = FINGERPRINT
+            .to_uppercase()
+            .as_bytes()
+            .chunks(2)
+            .map(|pair| std::str::from_utf8(pair).unwrap())
+            .collect::<Vec<_>>()
+            .join(":");
+        assert_eq!(fingerprint(&colons).unwrap(), FINGERPRINT);
+        let err = fingerprint("abcd").unwrap_err();
+        assert!(matches!(err, Error::Config(_)), "{}", err);
+    }
+}
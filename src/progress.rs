@@ -0,0 +1,256 @@
+/*! Download progress reporting
+
+`Download` reports its progress to a `ProgressReporter`, by default an `indicatif`
+progress bar drawn in the terminal, or a `LogReporter` logging the byte counts when
+stderr isn't a terminal. GUI apps and services can plug in their own reporter to drive
+a progress widget or log the progress instead, and a parent process can consume
+newline-delimited JSON events from a `JsonReporter`.
+*/
+use indicatif::{ProgressBar, ProgressStyle};
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+/// Receives the progress of a download
+pub trait ProgressReporter: Send + Sync {
+    /// The download started, `total` is its size in bytes when the server reported it
+    fn on_start(&self, total: Option<u64>);
+
+    /// `downloaded` bytes were received so far
+    fn on_progress(&self, downloaded: u64);
+
+    /// The whole response was received
+    fn on_finish(&self);
+}
+
+/// Default `ProgressReporter`, drawing an `indicatif` progress bar.
+/// No bar is shown when the size of the download is unknown.
+pub struct BarReporter {
+    style: ProgressStyle,
+    bar: Mutex<Option<ProgressBar>>,
+}
+
+impl BarReporter {
+    pub fn new(style: ProgressStyle) -> Self {
+        BarReporter {
+            style,
+            bar: Mutex::new(None),
+        }
+    }
+}
+
+impl ProgressReporter for BarReporter {
+    fn on_start(&self, total: Option<u64>) {
+        let bar = total.filter(|total| *total > 0).map(|total| {
+            let bar = ProgressBar::new(total);
+            bar.set_style(self.style.clone());
+            bar
+        });
+        *self.bar.lock().unwrap() = bar;
+    }
+
+    fn on_progress(&self, downloaded: u64) {
+        if let Some(ref bar) = *self.bar.lock().unwrap() {
+            bar.set_position(std::cmp::min(downloaded, bar.length()));
+        }
+    }
+
+    fn on_finish(&self) {
+        if let Some(bar) = self.bar.lock().unwrap().take() {
+            bar.finish_with_message("Done");
+        }
+    }
+}
+
+/// Bytes between two progress logs of a `LogReporter` when the size is unknown
+const LOG_PROGRESS_STEP: u64 = 10 * 1024 * 1024;
+
+/// `ProgressReporter` logging the downloaded byte count every 10 percent, or every 10MiB
+/// without a known size, through `info!`. Used instead of the progress bar when stderr
+/// isn't a terminal, e.g. in CI logs.
+#[derive(Default)]
+pub struct LogReporter {
+    // total size and downloaded bytes, as last logged
+    state: Mutex<(Option<u64>, u64)>,
+}
+
+impl LogReporter {
+    pub fn new() -> Self {
+        LogReporter::default()
+    }
+}
+
+impl ProgressReporter for LogReporter {
+    fn on_start(&self, total: Option<u64>) {
+        *self.state.lock().unwrap() = (total, 0);
+        info!("Download started, total:{:?} bytes", total);
+    }
+
+    fn on_progress(&self, downloaded: u64) {
+        let mut state = self.state.lock().unwrap();
+        let (total, logged) = *state;
+        let due = match total.filter(|total| *total > 0) {
+            Some(total) => downloaded * 10 / total > logged * 10 / total,
+            None => downloaded >= logged + LOG_PROGRESS_STEP,
+        };
+        if due {
+            state.1 = downloaded;
+            info!("Downloaded {} of {:?} bytes", downloaded, total);
+        }
+    }
+
+    fn on_finish(&self) {
+        let (total, logged) = *self.state.lock().unwrap();
+        info!("Download finished, {} bytes", total.unwrap_or(logged));
+    }
+}
+
+/// Bytes between two progress events of a `JsonReporter` when the size is unknown
+const JSON_PROGRESS_STEP: u64 = 1024 * 1024;
+
+/// `ProgressReporter` writing newline-delimited JSON events, e.g.
+/// `{"phase":"download","event":"progress","downloaded":123,"total":456}`, to stdout
+/// by default. `event` is `start`, `progress` or `finish`, `total` is `null` when the
+/// size is unknown. Progress events are emitted for every percent downloaded, or every
+/// MiB without a known size.
+pub struct JsonReporter {
+    writer: Mutex<Box<dyn Write + Send>>,
+    // total size and downloaded bytes, as last reported
+    state: Mutex<(Option<u64>, u64)>,
+}
+
+impl JsonReporter {
+    pub fn new() -> Self {
+        JsonReporter::with_writer(Box::new(io::stdout()))
+    }
+
+    /// Write the events to `writer` instead of stdout
+    pub fn with_writer(writer: Box<dyn Write + Send>) -> Self {
+        JsonReporter {
+            writer: Mutex::new(writer),
+            state: Mutex::new((None, 0)),
+        }
+    }
+
+    fn emit(&self, event: &str, downloaded: u64, total: Option<u64>) {
+        let line = serde_json::json!({
+            "phase": "download",
+            "event": event,
+            "downloaded": downloaded,
+            "total": total,
+        });
+        write_json_line(&mut **self.writer.lock().unwrap(), &line);
+    }
+}
+
+impl Default for JsonReporter {
+    fn default() -> Self {
+        JsonReporter::new()
+    }
+}
+
+impl ProgressReporter for JsonReporter {
+    fn on_start(&self, total: Option<u64>) {
+        *self.state.lock().unwrap() = (total, 0);
+        self.emit("start", 0, total);
+    }
+
+    fn on_progress(&self, downloaded: u64) {
+        let mut state = self.state.lock().unwrap();
+        let (total, reported) = *state;
+        let due = match total.filter(|total| *total > 0) {
+            Some(total) => downloaded * 100 / total > reported * 100 / total,
+            None => downloaded >= reported + JSON_PROGRESS_STEP,
+        };
+        if due {
+            state.1 = downloaded;
+            drop(state);
+            self.emit("progress", downloaded, total);
+        }
+    }
+
+    fn on_finish(&self) {
+        let (total, reported) = *self.state.lock().unwrap();
+        self.emit("finish", total.unwrap_or(reported), total);
+    }
+}
+
+/// Write `value` to `writer` as a line of JSON, flushed right away for the reading process.
+/// Failures are ignored, progress output mustn't fail the update.
+pub(crate) fn write_json_line(writer: &mut dyn Write, value: &serde_json::Value) {
+    let _ = writeln!(writer, "{}", value).and_then(|_| writer.flush());
+}
+
+/// `ProgressReporter` shared by an updater and the downloads it starts
+#[derive(Clone)]
+pub struct SharedReporter(Arc<dyn ProgressReporter>);
+
+impl SharedReporter {
+    pub fn new(reporter: Box<dyn ProgressReporter>) -> Self {
+        SharedReporter(Arc::from(reporter))
+    }
+}
+
+impl ProgressReporter for SharedReporter {
+    fn on_start(&self, total: Option<u64>) {
+        self.0.on_start(total)
+    }
+
+    fn on_progress(&self, downloaded: u64) {
+        self.0.on_progress(downloaded)
+    }
+
+    fn on_finish(&self) {
+        self.0.on_finish()
+    }
+}
+
+impl std::fmt::Debug for SharedReporter {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("SharedReporter")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `Write` appending to a shared buffer
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn json_progress_events() {
+        let buffer = Buffer::default();
+        let reporter = JsonReporter::with_writer(Box::new(buffer.clone()));
+        reporter.on_start(Some(200));
+        for downloaded in 1..=200 {
+            reporter.on_progress(downloaded);
+        }
+        reporter.on_finish();
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let events = output
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        // start, one per percent, finish
+        assert_eq!(events.len(), 102);
+        assert_eq!(
+            events[0],
+            serde_json::json!({"phase": "download", "event": "start", "downloaded": 0, "total": 200})
+        );
+        assert_eq!(events[50]["downloaded"], 100);
+        assert_eq!(events[101]["event"], "finish");
+        assert_eq!(events[101]["downloaded"], 200);
+    }
+}
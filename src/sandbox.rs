@@ -0,0 +1,336 @@
+/*! Reduced-privilege downloads
+
+Runs the network portion of an update in a helper process with reduced privileges, so
+parsing untrusted responses doesn't happen with the privileges needed to replace the
+installed binary. The helper re-executes the current executable, which has to hand it
+over to `serve_helper` first thing in `main`:
+
+```rust,no_run
+// first thing in `main`
+update::sandbox::serve_helper();
+```
+
+The helper only hands back the downloaded file, which the parent checks and copies
+before verifying and installing it. Sandboxed downloads are only supported on unix when
+running as root, they fail with an `Error::Config` otherwise.
+*/
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::{client::ClientOptions, errors::*, Download};
+
+/// Env var telling the re-executed current executable to serve a sandboxed download
+const HELPER_ENV: &str = "SELF_UPDATE_SANDBOX_HELPER";
+
+/// User and group the helper switches to (`nobody`)
+#[cfg(unix)]
+const UNPRIVILEGED_ID: u32 = 65534;
+
+/// File the helper downloads into, in its work dir
+const OUTPUT_FILE: &str = "download";
+
+/// Download handed to the helper on its stdin
+#[derive(Serialize, Deserialize)]
+struct HelperRequest {
+    url: String,
+    headers: Vec<(String, Vec<u8>)>,
+    verify_header_digests: bool,
+    free_space: Option<(Vec<PathBuf>, u64)>,
+    client_options: ClientOptions,
+}
+
+/// Serve the sandboxed download when the current process is the helper started for it,
+/// exiting once it's done. Returns right away otherwise.
+///
+/// Must be called at the start of `main`, before any thread is started, by executables
+/// using `sandbox_download`.
+pub fn serve_helper() {
+    if std::env::var_os(HELPER_ENV).is_none() {
+        return;
+    }
+    let code = match serve() {
+        Ok(()) => 0,
+        Err(e) => {
+            eprint!("{}", e);
+            1
+        }
+    };
+    std::process::exit(code)
+}
+
+impl HelperRequest {
+    #[cfg(unix)]
+    fn new(download: &Download, client_options: &ClientOptions) -> Self {
+        HelperRequest {
+            url: download.url.clone(),
+            headers: download
+                .headers
+                .iter()
+                .map(|(name, value)| (name.as_str().to_owned(), value.as_bytes().to_owned()))
+                .collect(),
+            verify_header_digests: download.verify_header_digests,
+            free_space: download.free_space.clone(),
+            client_options: client_options.clone(),
+        }
+    }
+
+    // The requested download, with a client of its own
+    fn into_download(self) -> Result<Download> {
+        let mut headers = reqwest::header::HeaderMap::new();
+        for (name, value) in self.headers {
+            let name = reqwest::header::HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| format_err!(Error::Config, "Invalid header name {:?}: {}", name, e))?;
+            let value = reqwest::header::HeaderValue::from_bytes(&value).map_err(|e| {
+                format_err!(Error::Config, "Invalid value of header {}: {}", name, e)
+            })?;
+            headers.append(name, value);
+        }
+        let mut download = Download::from_url(&self.url);
+        download
+            .set_headers(headers)
+            .verify_header_digests(self.verify_header_digests)
+            .set_client(self.client_options.build_new_client()?);
+        if let Some((dirs, margin)) = self.free_space {
+            download.require_free_space(dirs, margin);
+        }
+        Ok(download)
+    }
+}
+
+// Run the download read from stdin into the `OUTPUT_FILE` of the current dir
+fn serve() -> Result<()> {
+    let request: HelperRequest = serde_json::from_reader(std::io::stdin())?;
+    request
+        .into_download()?
+        .download_to(std::fs::File::create(OUTPUT_FILE)?)
+}
+
+/// Run `download` into `dest` in the helper process, with the http client built from
+/// `client_options`.
+///
+/// The helper runs as `nobody`, in a work dir handed over to it for the duration of the
+/// download, with `no_new_privs` set on linux. Fails with `Error::Config` when not running
+/// as root, as the helper couldn't switch users.
+/// The work dir is created in the system temp dir, and the current executable must be
+/// executable by the helper's user. Only the parent writes `dest`, once the downloaded
+/// file is checked.
+#[cfg(unix)]
+pub(crate) fn download(
+    download: &Download,
+    client_options: &ClientOptions,
+    dest: &Path,
+) -> Result<()> {
+    use std::io::Write;
+    use std::os::unix::process::CommandExt;
+
+    if std::env::var_os(HELPER_ENV).is_some() {
+        bail!(
+            Error::Config,
+            "Sandboxed download started by its own helper, `sandbox::serve_helper` must be \
+             called at the start of `main`"
+        );
+    }
+    let owner = unsafe { (libc::geteuid(), libc::getegid()) };
+    if owner.0 != 0 {
+        bail!(
+            Error::Config,
+            "Sandboxed downloads require running as root, to run the helper as another user"
+        );
+    }
+    let request = serde_json::to_vec(&HelperRequest::new(download, client_options))?;
+
+    // a fresh dir, nothing the helper could leave behind is trusted
+    let work = tempfile::Builder::new()
+        .prefix("self_update_sandbox")
+        .tempdir()?;
+    std::os::unix::fs::lchown(work.path(), Some(UNPRIVILEGED_ID), Some(UNPRIVILEGED_ID))?;
+
+    let mut command = std::process::Command::new(helper_exe()?);
+    command
+        .args(helper_args())
+        .env(HELPER_ENV, "1")
+        .current_dir(work.path())
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::piped())
+        // supplementary groups are dropped along with the user switch
+        .uid(UNPRIVILEGED_ID)
+        .gid(UNPRIVILEGED_ID);
+    unsafe {
+        command.pre_exec(|| {
+            #[cfg(target_os = "linux")]
+            {
+                if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+    let mut child = command.spawn().map_err(|e| {
+        format_err!(
+            Error::Update,
+            "Failed to start the sandboxed download: {}",
+            e
+        )
+    })?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // a helper failing early closes its stdin, its exit status tells why
+        let _ = stdin.write_all(&request);
+    }
+    let output = child.wait_with_output()?;
+
+    // take the dir back before looking at its content, so it can't change meanwhile
+    std::os::unix::fs::lchown(work.path(), Some(owner.0), Some(owner.1))?;
+    if !output.status.success() {
+        let msg = String::from_utf8_lossy(&output.stderr);
+        let msg = match msg.trim() {
+            "" => format!("helper exited abnormally ({})", output.status),
+            msg => msg.to_owned(),
+        };
+        bail!(Error::Update, "Sandboxed download failed: {}", msg)
+    }
+    check_work_dir(work.path())?;
+    // a copy, the helper's user may still hold the downloaded file open
+    std::fs::copy(work.path().join(OUTPUT_FILE), dest)?;
+    Ok(())
+}
+
+/// Sandboxed downloads aren't supported on this platform, fails with `Error::Config`
+#[cfg(not(unix))]
+pub(crate) fn download(
+    _download: &Download,
+    _client_options: &ClientOptions,
+    _dest: &Path,
+) -> Result<()> {
+    bail!(
+        Error::Config,
+        "Sandboxed downloads aren't supported on this platform"
+    )
+}
+
+// Check the helper's work `dir` only holds the downloaded file, a regular file and not a
+// link to a file the helper's user can't read
+#[cfg(unix)]
+fn check_work_dir(dir: &Path) -> Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut found = false;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let meta = std::fs::symlink_metadata(entry.path())?;
+        if entry.file_name() != OUTPUT_FILE || !meta.file_type().is_file() || meta.nlink() > 1 {
+            bail!(
+                Error::Update,
+                "Unexpected entry {:?} left by the sandboxed download",
+                entry.file_name()
+            )
+        }
+        found = true;
+    }
+    if !found {
+        bail!(Error::Update, "The sandboxed download left no file")
+    }
+    Ok(())
+}
+
+// Executable the helper runs
+#[cfg(all(unix, not(test)))]
+fn helper_exe() -> Result<PathBuf> {
+    Ok(std::env::current_exe()?)
+}
+
+// Args of the helper, none as `serve_helper` is called at the start of `main`
+#[cfg(all(unix, not(test)))]
+fn helper_args() -> Vec<&'static str> {
+    vec![]
+}
+
+// The test binary is copied where the unprivileged user can execute it
+#[cfg(all(unix, test))]
+fn helper_exe() -> Result<PathBuf> {
+    use std::os::unix::fs::PermissionsExt;
+    static EXE: std::sync::OnceLock<PathBuf> = std::sync::OnceLock::new();
+
+    if let Some(exe) = EXE.get() {
+        return Ok(exe.clone());
+    }
+    let dir = std::env::temp_dir().join(format!("self_update_sandbox_{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755))?;
+    let exe = dir.join("helper");
+    std::fs::copy(std::env::current_exe()?, &exe)?;
+    Ok(EXE.get_or_init(|| exe).clone())
+}
+
+// The test binary only runs the test serving the helper
+#[cfg(all(unix, test))]
+fn helper_args() -> Vec<&'static str> {
+    vec!["--exact", "sandbox::tests::helper", "--nocapture"]
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    // Run by the helper process
+    #[test]
+    fn helper() {
+        serve_helper();
+    }
+
+    #[test]
+    fn sandboxed_download() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/asset", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let n = conn.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            conn.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 4\r\n\r\ndata")
+                .unwrap();
+            String::from_utf8(request).unwrap().to_lowercase()
+        });
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("asset");
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert("x-token", "secret".parse().unwrap());
+        let mut asset = Download::from_url(&url);
+        asset.set_headers(headers);
+        download(&asset, &ClientOptions::default(), &dest).unwrap();
+        assert!(server.join().unwrap().contains("x-token: secret"));
+        assert_eq!(std::fs::read(&dest).unwrap(), b"data");
+
+        // nothing listens on port 1
+        let unreachable = Download::from_url("http://127.0.0.1:1/asset");
+        let err = download(&unreachable, &ClientOptions::default(), &dest).unwrap_err();
+        assert!(
+            err.to_string().contains("Sandboxed download failed"),
+            "{}",
+            err
+        );
+        let _ = std::fs::remove_dir_all(helper_exe().unwrap().parent().unwrap());
+    }
+
+    #[test]
+    fn work_dir_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = check_work_dir(dir.path()).unwrap_err();
+        assert!(err.to_string().contains("no file"), "{}", err);
+
+        std::os::unix::fs::symlink("/etc/shadow", dir.path().join(OUTPUT_FILE)).unwrap();
+        let err = check_work_dir(dir.path()).unwrap_err();
+        assert!(err.to_string().contains("Unexpected entry"), "{}", err);
+
+        std::fs::remove_file(dir.path().join(OUTPUT_FILE)).unwrap();
+        std::fs::write(dir.path().join(OUTPUT_FILE), b"data").unwrap();
+        check_work_dir(dir.path()).unwrap();
+        std::fs::hard_link(dir.path().join(OUTPUT_FILE), dir.path().join("other")).unwrap();
+        assert!(check_work_dir(dir.path()).is_err());
+    }
+}
@@ -0,0 +1,219 @@
+/*! Service control
+
+Stopping the service running the installed binary before it's replaced and starting it
+again afterwards, see `service_name`. The service manager is detected at runtime:
+systemd (`systemctl`), runit (`sv`) or the Windows service control manager (`sc`).
+*/
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use crate::{
+    errors::*,
+    update::{run_hook_command, run_with_timeout, HookOutput, ReleaseUpdate, DEFAULT_HOOK_TIMEOUT},
+};
+
+/// `HookOutput::stage` of stopping the `service_name`
+pub(crate) const STOP_STAGE: &str = "Stop service";
+
+/// `HookOutput::stage` of starting the `service_name`
+pub(crate) const START_STAGE: &str = "Start service";
+
+// Interval a pending Windows service's state is queried at
+const STATE_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Service manager a service is controlled through
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ServiceManager {
+    /// systemd, through `systemctl`
+    Systemd,
+    /// runit, through `sv`
+    Runit,
+    /// Windows service control manager, through `sc`
+    Windows,
+}
+
+impl ServiceManager {
+    /// Detect the service manager of this machine, `None` when there's no supported one
+    pub fn detect() -> Option<Self> {
+        if cfg!(windows) {
+            Some(ServiceManager::Windows)
+        } else if Path::new("/run/systemd/system").is_dir() {
+            Some(ServiceManager::Systemd)
+        } else if in_path("sv") {
+            Some(ServiceManager::Runit)
+        } else {
+            None
+        }
+    }
+
+    /// Stop the service `name`, returning the output of the command. On Windows, waits
+    /// for the service to be stopped.
+    pub fn stop(&self, name: &str) -> Result<HookOutput> {
+        self.run(STOP_STAGE, "stop", name, DEFAULT_HOOK_TIMEOUT)
+    }
+
+    /// Start the service `name`, returning the output of the command. On Windows, waits
+    /// for the service to be running.
+    pub fn start(&self, name: &str) -> Result<HookOutput> {
+        self.run(START_STAGE, "start", name, DEFAULT_HOOK_TIMEOUT)
+    }
+
+    // Run `action` on the service `name`, failing if it isn't done within `timeout`
+    fn run(&self, stage: &str, action: &str, name: &str, timeout: Duration) -> Result<HookOutput> {
+        let started = Instant::now();
+        let command = self.command(action, name);
+        let cmd = format!("{} {} {}", self.program(), action, name);
+        let output = run_hook_command(stage, cmd, command, timeout)?;
+        if *self == ServiceManager::Windows {
+            // `sc` returns as soon as the request is sent, while the service is still pending
+            let state = if action == "stop" {
+                "STOPPED"
+            } else {
+                "RUNNING"
+            };
+            wait_for_state(name, state, started, timeout)?;
+        }
+        Ok(output)
+    }
+
+    fn program(&self) -> &'static str {
+        match self {
+            ServiceManager::Systemd => "systemctl",
+            ServiceManager::Runit => "sv",
+            ServiceManager::Windows => "sc",
+        }
+    }
+
+    // Command running `action` (`stop` or `start`) on the service `name`
+    fn command(&self, action: &str, name: &str) -> Command {
+        let mut command = Command::new(self.program());
+        command.arg(action).arg(name);
+        command
+    }
+}
+
+// Query the Windows service `name` until it's in `state`, until `timeout` elapsed since
+// `started`
+fn wait_for_state(name: &str, state: &str, started: Instant, timeout: Duration) -> Result<()> {
+    loop {
+        let remaining = timeout.saturating_sub(started.elapsed());
+        let mut command = Command::new("sc");
+        command.arg("query").arg(name);
+        let output = run_with_timeout(command, remaining)
+            .map_err(|e| format_err!(Error::Update, "Failed to query service {:?}: {}", name, e))?;
+        let current = query_state(&String::from_utf8_lossy(&output.stdout));
+        if current.as_deref() == Some(state) {
+            return Ok(());
+        }
+        if started.elapsed() + STATE_POLL_INTERVAL >= timeout {
+            bail!(
+                Error::Update,
+                "Service {:?} not {} within {:?}, state: {}",
+                name,
+                state,
+                timeout,
+                current.as_deref().unwrap_or("unknown")
+            )
+        }
+        std::thread::sleep(STATE_POLL_INTERVAL);
+    }
+}
+
+// State reported by `sc query`, e.g. `STOP_PENDING` from `STATE : 3  STOP_PENDING`
+fn query_state(output: &str) -> Option<String> {
+    output
+        .lines()
+        .find(|line| line.trim_start().starts_with("STATE"))
+        .and_then(|line| line.split_whitespace().last())
+        .map(|state| state.to_owned())
+}
+
+// Check if `program` is found in one of the `PATH` dirs
+fn in_path(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(false)
+}
+
+// Stop the `service_name` of `updater`, a no-op when unset
+pub(crate) fn stop_service<U: ReleaseUpdate + ?Sized>(updater: &U) -> Result<Option<HookOutput>> {
+    control(updater, STOP_STAGE, "stop")
+}
+
+// Start the `service_name` of `updater`, a no-op when unset
+pub(crate) fn start_service<U: ReleaseUpdate + ?Sized>(updater: &U) -> Result<Option<HookOutput>> {
+    control(updater, START_STAGE, "start")
+}
+
+fn control<U: ReleaseUpdate + ?Sized>(
+    updater: &U,
+    stage: &str,
+    action: &str,
+) -> Result<Option<HookOutput>> {
+    let name = match updater.service_name() {
+        Some(name) => name,
+        None => return Ok(None),
+    };
+    let manager = ServiceManager::detect().ok_or_else(|| {
+        format_err!(
+            Error::Config,
+            "No supported service manager found to {} service {:?}",
+            action,
+            name
+        )
+    })?;
+    manager
+        .run(stage, action, &name, updater.hook_timeout())
+        .map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn service_commands() {
+        let command = |manager: ServiceManager, action| {
+            let command = manager.command(action, "agent");
+            let args = command
+                .get_args()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect::<Vec<_>>();
+            (command.get_program().to_string_lossy().into_owned(), args)
+        };
+        assert_eq!(
+            command(ServiceManager::Systemd, "stop"),
+            (
+                "systemctl".to_owned(),
+                vec!["stop".to_owned(), "agent".to_owned()]
+            )
+        );
+        assert_eq!(
+            command(ServiceManager::Runit, "start"),
+            (
+                "sv".to_owned(),
+                vec!["start".to_owned(), "agent".to_owned()]
+            )
+        );
+        assert_eq!(
+            command(ServiceManager::Windows, "stop"),
+            ("sc".to_owned(), vec!["stop".to_owned(), "agent".to_owned()])
+        );
+        assert!(in_path("sh") || cfg!(windows));
+        assert!(!in_path("no-such-program-for-sure"));
+    }
+
+    #[test]
+    fn windows_service_state() {
+        let output = "\r\nSERVICE_NAME: agent\r\n        \
+                      TYPE               : 10  WIN32_OWN_PROCESS\r\n        \
+                      STATE              : 3  STOP_PENDING\r\n        \
+                      WIN32_EXIT_CODE    : 0  (0x0)\r\n";
+        assert_eq!(query_state(output), Some("STOP_PENDING".to_owned()));
+        assert_eq!(
+            query_state("[SC] EnumQueryServicesStatus:OpenService FAILED 1060"),
+            None
+        );
+    }
+}
@@ -0,0 +1,59 @@
+/*! Signatures
+
+Verification of the detached [minisign](https://jedisct1.github.io/minisign/) signatures
+of downloads, enabled by the `signatures` feature.
+*/
+use crate::errors::*;
+
+/// Verify the minisign `signature` (the content of a `.minisig` file) of `data` against
+/// the public key `key`, either the base64 key line or the whole `minisign.pub` file
+#[cfg(feature = "signatures")]
+pub(crate) fn verify(key: &str, data: &[u8], signature: &str) -> Result<()> {
+    use minisign_verify::{PublicKey, Signature};
+
+    let key = key.trim();
+    let public_key = if key.contains('\n') {
+        PublicKey::decode(key)
+    } else {
+        PublicKey::from_base64(key)
+    }
+    .map_err(|e| format_err!(Error::Config, "Invalid verifying key: {}", e))?;
+    let signature = Signature::decode(signature.trim())
+        .map_err(|e| format_err!(Error::Signature, "Invalid signature: {}", e))?;
+    public_key
+        .verify(data, &signature, false)
+        .map_err(|e| format_err!(Error::Signature, "Signature verification failed: {}", e))
+}
+
+#[cfg(not(feature = "signatures"))]
+pub(crate) fn verify(_key: &str, _data: &[u8], _signature: &str) -> Result<()> {
+    bail!(
+        Error::Config,
+        "Verifying signatures requires the `signatures` feature"
+    )
+}
+
+#[cfg(all(test, feature = "signatures"))]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3";
+    const SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RUQf6LRCGA9i559r3g7V1qNyJDApGip8MfqcadIgT9CuhV3EMhHoN1mGTkUidF/z7SrlQgXdy8ofjb7bNJJylDOocrCo8KLzZwo=
+trusted comment: timestamp:1633700835\tfile:test\tprehashed
+wLMDjy9FLAuxZ3q4NlEvkgtyhrr0gtTu6KC4KBJdITbbOeAi1zBIYo0v4iTgt8jJpIidRJnp94ABQkJAgAooBQ==";
+
+    #[test]
+    fn verify_signature() {
+        verify(KEY, b"test", SIGNATURE).unwrap();
+        let pub_file = format!("untrusted comment: minisign public key\n{}\n", KEY);
+        verify(&pub_file, b"test", SIGNATURE).unwrap();
+
+        let err = verify(KEY, b"tampered", SIGNATURE).unwrap_err();
+        assert!(matches!(err, Error::Signature(_)), "{}", err);
+        let err = verify(KEY, b"test", "not a signature").unwrap_err();
+        assert!(matches!(err, Error::Signature(_)), "{}", err);
+        let err = verify("not a key", b"test", SIGNATURE).unwrap_err();
+        assert!(matches!(err, Error::Config(_)), "{}", err);
+    }
+}
@@ -1,45 +1,372 @@
 use indicatif::ProgressStyle;
 use reqwest::{self, header};
+use serde::{Deserialize, Serialize};
 use std::env;
-use std::fs;
 #[cfg(not(windows))]
 use std::os::unix::fs::PermissionsExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use crate::{confirm, errors::*, version, Download, Extract, Move, Status};
+use crate::{client::ClientOptions, errors::*, DownloadStats, Status};
+
+/// Policy applied when the install path already holds something that doesn't look like
+/// the binary being updated, e.g. a file of another product or a misconfigured path
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ConflictPolicy {
+    /// Replace the existing file anyway
+    #[default]
+    Overwrite,
+    /// Abort the update with an `Error::Update`, before any hook runs
+    Fail,
+    /// Move the existing file aside (`<path>.conflict.<unix-timestamp>`) before replacing it
+    Backup,
+}
 
 /// Release asset information
 #[derive(Clone, Debug, Default)]
 pub struct ReleaseAsset {
     pub download_url: String,
     pub name: String,
+    /// Checksum of the asset as recorded by the backend, if any
+    pub hash: Option<String>,
+    /// Size of the asset in bytes, if known
+    pub size: Option<u64>,
+    /// Url of the asset's detached minisign signature, if published
+    pub signature_url: Option<String>,
+}
+
+/// Result of verifying the installed binary against the checksum recorded for its version
+#[derive(Clone, Debug)]
+pub struct VerifyReport {
+    /// Version the installed binary was verified against
+    pub version: String,
+    /// Path of the verified binary
+    pub path: PathBuf,
+    /// Algorithm of the checksums, e.g. `sha256`
+    pub algorithm: String,
+    /// Checksum recorded by the backend, `None` if the release has none
+    pub expected: Option<String>,
+    /// Checksum of the installed binary, `None` if it's missing
+    pub actual: Option<String>,
+}
+
+impl VerifyReport {
+    /// Returns `true` if the binary exists and matches the recorded checksum (if any)
+    pub fn intact(&self) -> bool {
+        match (&self.expected, &self.actual) {
+            (_, None) => false,
+            (None, Some(_)) => true,
+            (Some(expected), Some(actual)) => {
+                crate::trim_text(expected).eq_ignore_ascii_case(crate::trim_text(actual))
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for VerifyReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} version {} ({}): expected {}, found {}",
+            self.path,
+            self.version,
+            self.algorithm,
+            self.expected.as_deref().unwrap_or("<no record>"),
+            self.actual.as_deref().unwrap_or("<missing>")
+        )
+    }
+}
+
+/// Policy applied when the installed binary reports a version other than the one
+/// advertised by the server
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum VersionMatch {
+    /// Don't check the installed binary's version
+    #[default]
+    Off,
+    /// Log a warning on mismatch, or when the version can't be checked
+    Warn,
+    /// Fail the update on mismatch, or when the version can't be checked
+    Fail,
+}
+
+/// Format of the output printed while updating, when `show_output` is set
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    /// Messages and an `indicatif` progress bar for a terminal
+    #[default]
+    Human,
+    /// Newline-delimited JSON events on stdout, for a parent process to consume, e.g.
+    /// `{"phase":"download","event":"progress","downloaded":123,"total":456}`.
+    /// See `progress::JsonReporter`
+    Json,
+}
+
+/// Scheme of the `Authorization` header the auth token is sent with
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum AuthScheme {
+    /// `Bearer <token>`
+    #[default]
+    Bearer,
+    /// `token <token>`, as expected by GitHub
+    Token,
+    /// HTTP Basic auth with `username` and `password`, the auth token isn't sent
+    Basic { username: String, password: String },
+}
+
+impl AuthScheme {
+    /// Value of the `Authorization` header for `auth_token`, `None` without credentials
+    pub(crate) fn header_value(&self, auth_token: Option<&str>) -> Option<String> {
+        match *self {
+            AuthScheme::Bearer => auth_token.map(|token| format!("Bearer {}", token)),
+            AuthScheme::Token => auth_token.map(|token| format!("token {}", token)),
+            AuthScheme::Basic {
+                ref username,
+                ref password,
+            } => Some(format!(
+                "Basic {}",
+                base64::encode(format!("{}:{}", username, password))
+            )),
+        }
+    }
+}
+
+/// Description of the action an update would perform, produced in dry-run mode
+#[derive(Clone, Debug, Serialize)]
+pub struct UpdatePlan {
+    pub bin_name: String,
+    pub current_version: String,
+    pub target_version: String,
+    pub asset_name: String,
+    pub asset_url: String,
+    pub checksum: Option<String>,
+    pub install_path: PathBuf,
+    pub before_cmd: Option<String>,
+    pub after_cmd: Option<String>,
+}
+
+/// Boxed future returned by the async `ReleaseUpdate` methods
+#[cfg(feature = "async")]
+pub type AsyncResult<'a, T> =
+    std::pin::Pin<Box<dyn std::future::Future<Output = Result<T>> + Send + 'a>>;
+
+/// Transform applied to the downloaded bytes before extraction, e.g. to decrypt
+/// an asset encrypted at rest
+#[derive(Clone)]
+pub struct DownloadTransform(Arc<TransformFn>);
+
+type TransformFn = dyn Fn(&[u8]) -> Result<Vec<u8>> + Send + Sync;
+
+impl DownloadTransform {
+    pub fn new<F>(transform: F) -> Self
+    where
+        F: Fn(&[u8]) -> Result<Vec<u8>> + Send + Sync + 'static,
+    {
+        DownloadTransform(Arc::new(transform))
+    }
+
+    /// Apply the transform to `data`
+    pub fn apply(&self, data: &[u8]) -> Result<Vec<u8>> {
+        (self.0)(data)
+    }
+}
+
+impl std::fmt::Debug for DownloadTransform {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("DownloadTransform")
+    }
+}
+
+/// Normalization applied to both version strings before they're compared, e.g. to
+/// strip a product name from `Version 9.9.10`
+#[derive(Clone)]
+pub struct VersionNormalizer(Arc<NormalizeFn>);
+
+type NormalizeFn = dyn Fn(&str) -> String + Send + Sync;
+
+impl VersionNormalizer {
+    pub fn new<F>(normalizer: F) -> Self
+    where
+        F: Fn(&str) -> String + Send + Sync + 'static,
+    {
+        VersionNormalizer(Arc::new(normalizer))
+    }
+
+    /// Normalize `ver`
+    pub fn apply(&self, ver: &str) -> String {
+        (self.0)(ver)
+    }
+}
+
+impl Default for VersionNormalizer {
+    fn default() -> Self {
+        VersionNormalizer::new(crate::version::normalize)
+    }
+}
+
+impl std::fmt::Debug for VersionNormalizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("VersionNormalizer")
+    }
+}
+
+/// Predicate telling if it's safe to install a downloaded release, e.g. when
+/// the application is idle
+#[derive(Clone)]
+pub struct CanApply(Arc<CanApplyFn>);
+
+type CanApplyFn = dyn Fn() -> bool + Send + Sync;
+
+impl CanApply {
+    pub fn new<F>(predicate: F) -> Self
+    where
+        F: Fn() -> bool + Send + Sync + 'static,
+    {
+        CanApply(Arc::new(predicate))
+    }
+
+    /// Evaluate the predicate
+    pub fn check(&self) -> bool {
+        (self.0)()
+    }
+}
+
+impl std::fmt::Debug for CanApply {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("CanApply")
+    }
+}
+
+/// Reader and writer the confirmation prompt is shown with instead of stdin/stdout,
+/// e.g. to confirm updates in a TUI
+#[derive(Clone)]
+pub struct ConfirmPrompt(Arc<Mutex<ConfirmIo>>);
+
+type ConfirmIo = (
+    Box<dyn std::io::BufRead + Send>,
+    Box<dyn std::io::Write + Send>,
+);
+
+impl ConfirmPrompt {
+    pub fn new<R, W>(reader: R, writer: W) -> Self
+    where
+        R: std::io::BufRead + Send + 'static,
+        W: std::io::Write + Send + 'static,
+    {
+        ConfirmPrompt(Arc::new(Mutex::new((Box::new(reader), Box::new(writer)))))
+    }
+
+    /// Write `msg` and read the response, a blank response or `y` confirms
+    pub fn confirm(&self, msg: &str) -> Result<()> {
+        let mut io = self
+            .0
+            .lock()
+            .map_err(|_| format_err!(Error::Update, "Confirmation prompt lock poisoned"))?;
+        let (ref mut reader, ref mut writer) = *io;
+        crate::confirm_with(msg, reader, writer)
+    }
+}
+
+impl std::fmt::Debug for ConfirmPrompt {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("ConfirmPrompt")
+    }
+}
+
+/// Marker written to `success_marker_path` once an update completed
+#[derive(Clone, Debug, Serialize)]
+pub struct SuccessMarker {
+    /// Version that was installed
+    pub version: String,
+    /// Completion time, in seconds since the unix epoch
+    pub timestamp: u64,
+}
+
+/// Output of a hook command, see `ReleaseUpdate::before_update` and `after_update`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HookOutput {
+    /// Hook the command ran in, `Before update` or `After update`, or `Stop service` and
+    /// `Start service` for the `service_name`
+    pub stage: String,
+    /// The command line
+    pub cmd: String,
+    /// Exit code, `None` when terminated by a signal
+    pub code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl HookOutput {
+    /// Returns `true` if the command exited with code 0
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
 }
 
 /// Update status with extended information
 pub enum UpdateStatus {
-    /// Crate is up to date
-    UpToDate,
-    /// Crate was updated to the contained release
-    Updated(Release),
+    /// Crate is up to date. Contains the current version
+    UpToDate(String),
+    /// Crate was updated from the contained version to the contained release, with the
+    /// stats of its download when it was downloaded by this update, and the output of the
+    /// hook commands that ran
+    Updated(String, Release, Option<DownloadStats>, Vec<HookOutput>),
+    /// Dry-run, nothing was changed. Contains the planned update
+    DryRun(UpdatePlan),
+    /// The missing or corrupt binary was reinstalled from the contained release,
+    /// along with the failed verification's report
+    Repaired(Release, VerifyReport),
 }
 
 impl UpdateStatus {
     /// Turn the extended information into the crate's standard `Status` enum
     pub fn into_status(self, current_version: String) -> Status {
         match self {
-            UpdateStatus::UpToDate => Status::UpToDate(current_version),
-            UpdateStatus::Updated(release) => Status::Updated(release.version),
+            UpdateStatus::UpToDate(_) => Status::UpToDate(current_version),
+            UpdateStatus::Updated(_, release, stats, hooks) => {
+                Status::Updated(release.version, stats, hooks)
+            }
+            UpdateStatus::DryRun(plan) => Status::DryRun(plan),
+            UpdateStatus::Repaired(release, _) => Status::Updated(release.version, None, vec![]),
         }
     }
 
     /// Returns `true` if `Status::UpToDate`
     pub fn uptodate(&self) -> bool {
-        matches!(*self, UpdateStatus::UpToDate)
+        matches!(*self, UpdateStatus::UpToDate(_))
     }
 
     /// Returns `true` if `Status::Updated`
     pub fn updated(&self) -> bool {
-        !self.uptodate()
+        matches!(*self, UpdateStatus::Updated(..))
+    }
+
+    /// Returns `true` if `Status::Repaired`
+    pub fn repaired(&self) -> bool {
+        matches!(*self, UpdateStatus::Repaired(..))
+    }
+
+    /// Returns `true` if `Status::DryRun`
+    pub fn dry_run(&self) -> bool {
+        matches!(*self, UpdateStatus::DryRun(_))
+    }
+}
+
+impl std::fmt::Display for UpdateStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            UpdateStatus::UpToDate(current) => write!(f, "already up to date ({})", current),
+            UpdateStatus::Updated(previous, release, ..) => {
+                write!(f, "updated {} → {}", previous, release.version)
+            }
+            UpdateStatus::DryRun(plan) => write!(
+                f,
+                "would update {} → {}",
+                plan.current_version, plan.target_version
+            ),
+            UpdateStatus::Repaired(release, _) => write!(f, "repaired {}", release.version),
+        }
     }
 }
 
@@ -49,29 +376,128 @@ pub struct Release {
     pub name: String,
     pub version: String,
     pub date: String,
+    /// Release notes, if published with the release
     pub body: Option<String>,
     pub assets: Vec<ReleaseAsset>,
+    /// Raw payload the release was parsed from, if retained by the backend (`keep_raw`)
+    pub raw: Option<serde_json::Value>,
 }
 
 impl Release {
-    /// Check if release has an asset who's name contains the specified `target`
+    /// Check if release has an asset who's name contains the specified `target`.
+    /// The target is matched as a substring, e.g. `x86_64-unknown-linux-gnu` matches
+    /// `app-1.2.0-x86_64-unknown-linux-gnu.tar.gz`
     pub fn has_target_asset(&self, target: &str) -> bool {
         self.assets.iter().any(|asset| asset.name.contains(target))
     }
 
     /// Return the first `ReleaseAsset` for the current release who's name
-    /// contains the specified `target`
+    /// contains the specified `target`, see `has_target_asset`
     pub fn asset_for(&self, target: &str) -> Option<ReleaseAsset> {
         self.assets
             .iter()
-            .filter(|asset| asset.name.contains(target))
+            .find(|asset| asset.name.contains(target))
             .cloned()
-            .next()
+    }
+
+    /// Summarize updating from the `current` version to this release, e.g.
+    /// `1.2.0 -> 1.3.0 (minor), released 2021-03-01, 4.20MB: Faster downloads`.
+    /// The date, size and first line of the body are included when known.
+    pub fn summary_from(&self, current: &str) -> String {
+        use crate::version;
+
+        let mut summary = format!("{} -> {}", current, self.version);
+        let kind = if version::bump_is_major(current, &self.version).unwrap_or(false) {
+            Some("major")
+        } else if version::bump_is_minor(current, &self.version).unwrap_or(false) {
+            Some("minor")
+        } else if version::bump_is_patch(current, &self.version).unwrap_or(false) {
+            Some("patch")
+        } else {
+            match version::bump_is_greater(current, &self.version) {
+                Ok(true) => Some("pre-release"),
+                Ok(false) if version::bump_is_greater(&self.version, current).unwrap_or(false) => {
+                    Some("downgrade")
+                }
+                Ok(false) => Some("same version"),
+                Err(_) => None,
+            }
+        };
+        if let Some(kind) = kind {
+            summary.push_str(&format!(" ({})", kind));
+        }
+        if !self.date.is_empty() {
+            summary.push_str(&format!(", released {}", self.date));
+        }
+        if let Some(size) = self.assets.first().and_then(|asset| asset.size) {
+            summary.push_str(&format!(", {}", indicatif::HumanBytes(size)));
+        }
+        let first_line = self
+            .body
+            .as_deref()
+            .and_then(|body| body.lines().map(str::trim).find(|line| !line.is_empty()));
+        if let Some(first_line) = first_line {
+            summary.push_str(&format!(": {}", first_line));
+        }
+        summary
+    }
+}
+
+impl std::fmt::Display for Release {
+    /// Renders e.g. `Agent 9.9.10 (2024-01-15)`, the name and date are omitted when empty
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if !self.name.is_empty() {
+            write!(f, "{} ", self.name)?;
+        }
+        f.write_str(&self.version)?;
+        if !self.date.is_empty() {
+            write!(f, " ({})", self.date)?;
+        }
+        Ok(())
     }
 }
 
+/// Return the release with the greatest semver version (a leading `v` is ignored),
+/// skipping releases whose version isn't semver
+pub fn latest_release(releases: Vec<Release>) -> Option<Release> {
+    let semver = |r: &Release| r.version.trim_start_matches('v').to_owned();
+    releases
+        .into_iter()
+        .filter(|r| semver::Version::parse(&semver(r)).is_ok())
+        .fold(None, |latest: Option<Release>, r| match latest {
+            Some(latest)
+                if !crate::version::bump_is_greater(&semver(&latest), &semver(&r))
+                    .unwrap_or(false) =>
+            {
+                Some(latest)
+            }
+            _ => Some(r),
+        })
+}
+
+/// Sort `releases` by descending semver version, a leading `v` ignored. Releases
+/// whose version isn't semver are sorted last, in their original order, with a warning.
+pub fn sort_releases(releases: Vec<Release>) -> Vec<Release> {
+    let mut keyed = releases
+        .into_iter()
+        .map(|r| {
+            let ver = semver::Version::parse(r.version.trim_start_matches('v')).ok();
+            if ver.is_none() {
+                warn!("Release version {:?} isn't semver, sorted last", r.version);
+            }
+            (ver, r)
+        })
+        .collect::<Vec<_>>();
+    // `None` sorts before any version, last once reversed
+    keyed.sort_by(|(a, _), (b, _)| b.cmp(a));
+    keyed.into_iter().map(|(_, r)| r).collect()
+}
+
 /// Updates to a specified or latest release
-pub trait ReleaseUpdate {
+///
+/// Implementors must be `Send + Sync`, so a `Box<dyn ReleaseUpdate>` can be moved to
+/// and shared with background threads, e.g. a polling loop.
+pub trait ReleaseUpdate: Send + Sync {
     /// Fetch details of the latest release from the backend
     fn get_latest_release(&self) -> Result<Release>;
 
@@ -102,186 +528,2775 @@ pub trait ReleaseUpdate {
     /// Flag indicating if process informative messages shall be output
     fn show_output(&self) -> bool;
 
-    // Flag indicating whether update when target version is heigher than current,default is false
+    /// Flag indicating whether to update even when the target version isn't greater than the current, default is false
     fn ignore_ver_compare(&self) -> bool {
-        return false;
+        false
+    }
+
+    /// Flag indicating whether build metadata (`+build.42`) is compared when the
+    /// current and target versions are otherwise equal, default is false
+    fn compare_build_metadata(&self) -> bool {
+        false
+    }
+
+    /// Policy applied when the install path holds something other than the binary being updated,
+    /// default is `ConflictPolicy::Overwrite`
+    fn on_existing_conflict(&self) -> ConflictPolicy {
+        ConflictPolicy::Overwrite
     }
 
     /// Flag indicating if the user shouldn't be prompted to confirm an update
     fn no_confirm(&self) -> bool;
 
-    /// Flag indicating if the idftiy the target platform,default is true
+    /// Flag indicating if the release asset must be the build for `target` (or the
+    /// `fallback_asset`), failing the update when there's none. Otherwise the first asset
+    /// is installed, unless the release has several and one matches the target.
+    /// Default is true
     fn idty_target_platform(&self) -> bool {
-        return true;
+        true
     }
 
-    //all replce the source dir to dst dir
+    /// Flag indicating if the downloaded archive holds the whole install dir: it's
+    /// extracted in place of `bin_install_path`, a directory holding `bin_name`, instead
+    /// of extracting `bin_path_in_archive` to replace the binary alone. Default is false,
+    /// calling the deprecated `all_replce`
+    fn replace_all(&self) -> bool {
+        #[allow(deprecated)]
+        self.all_replce()
+    }
+
+    /// Deprecated alias of `replace_all`, still honored when `replace_all` isn't overridden
+    #[deprecated(since = "0.23.0", note = "renamed to `replace_all`")]
     fn all_replce(&self) -> bool {
-        return false;
+        false
+    }
+
+    /// Flag indicating if the update should only be planned, without downloading,
+    /// replacing or running any hooks, default is false
+    fn dry_run(&self) -> bool {
+        false
+    }
+
+    /// Path the json `UpdatePlan` is written to in dry-run mode
+    fn dry_run_plan_path(&self) -> Option<PathBuf> {
+        None
+    }
+
+    /// Command run by `before_update`
+    fn before_cmd(&self) -> Option<String> {
+        None
+    }
+
+    /// Time the `before_cmd` and `after_cmd` are each allowed to run before they're killed,
+    /// failing the update. Also applies to the `on_success_cmd` and `on_failure_cmd`, which
+    /// don't change the outcome. Defaults to `DEFAULT_HOOK_TIMEOUT`
+    fn hook_timeout(&self) -> Duration {
+        DEFAULT_HOOK_TIMEOUT
+    }
+
+    /// Command run at the end of `update` when it succeeded, with `UPDATE_STATUS`
+    /// (`UpToDate` or `Updated`) and `UPDATE_VERSION` set in its environment
+    fn on_success_cmd(&self) -> Option<String> {
+        None
+    }
+
+    /// Command run at the end of `update` when it failed, with `UPDATE_ERROR_KIND`
+    /// (the `Error` variant, e.g. `Network`) and `UPDATE_ERROR` set in its environment
+    fn on_failure_cmd(&self) -> Option<String> {
+        None
+    }
+
+    /// Command run by `after_update`
+    fn after_cmd(&self) -> Option<String> {
+        None
+    }
+
+    /// Url a JSON notification (`name`, `old_version`, `new_version`, `host`) is POSTed to
+    /// through the `client` at the end of `update` when a release was installed. Failing to
+    /// notify is logged, the update still succeeds
+    fn on_complete_webhook(&self) -> Option<String> {
+        None
+    }
+
+    /// Name of the service running the binary, stopped through the detected
+    /// `ServiceManager` before `before_update` and started again once the release is
+    /// installed. No service is controlled when `None`, the default
+    fn service_name(&self) -> Option<String> {
+        None
+    }
+
+    /// Action before a downloaded release is installed, runs the `before_cmd` by default,
+    /// returning its output. Failing, e.g. when the command exits non-zero or times out,
+    /// aborts the update before anything is replaced
+    fn before_update(&self) -> Result<Option<HookOutput>> {
+        run_hook(self, "Before update", self.before_cmd())
+    }
+
+    /// Action after a release was installed, or failed to, runs the `after_cmd` by default,
+    /// returning its output. Failing, e.g. when the command exits non-zero or times out,
+    /// fails the update and rolls back the installed release
+    fn after_update(&self) -> Result<Option<HookOutput>> {
+        run_hook(self, "After update", self.after_cmd())
     }
-    /// action before the update start
-    fn before_update(&self) -> () {}
 
-    ///action after the update have finished
-    fn after_update(&self) -> () {}
+    /// Flag drawing the progress bar of `show_download_progress` even when stderr isn't a
+    /// terminal, where the downloaded byte counts are logged instead otherwise.
+    /// Default is false
+    fn force_progress(&self) -> bool {
+        false
+    }
 
     /// Styling for progress information if `show_download_progress` is set (see `indicatif::ProgressStyle`)
     fn progress_style(&self) -> Option<ProgressStyle>;
 
+    /// Format of the output printed when `show_output` is set. In `OutputFormat::Json` the
+    /// download progress is reported as JSON events instead of the progress bar, regardless
+    /// of `show_download_progress`. Defaults to `OutputFormat::Human`
+    fn output_format(&self) -> OutputFormat {
+        OutputFormat::Human
+    }
+
+    /// Reporter receiving the download progress instead of the progress bar, regardless of
+    /// `show_download_progress`. Defaults to `None`
+    fn progress_reporter(&self) -> Option<crate::progress::SharedReporter> {
+        None
+    }
+
+    /// Flag aborting the download with `Error::Cancelled` once it's set. Defaults to `None`
+    fn cancel_flag(&self) -> Option<Arc<std::sync::atomic::AtomicBool>> {
+        None
+    }
+
     /// Authorisation token for communicating with backend
     fn auth_token(&self) -> Option<String>;
 
+    /// Scheme of the `Authorization` header the `auth_token` is sent with,
+    /// default is `AuthScheme::default()`, `Bearer`, same as the builders
+    fn auth_scheme(&self) -> AuthScheme {
+        AuthScheme::default()
+    }
+
+    /// Caller-supplied headers added to api requests and downloads, overriding the
+    /// default headers
+    fn headers(&self) -> header::HeaderMap {
+        header::HeaderMap::new()
+    }
+
+    /// SHA-256 fingerprint of the server's leaf certificate, hex encoded, checked during
+    /// the TLS handshake of every request. The `client` and `async_client` must enforce
+    /// it themselves, e.g. built from the `client_options`, the clients built otherwise do.
+    /// Requires the `rustls-tls` feature. Defaults to `None`
+    fn pinned_cert_sha256(&self) -> Option<String> {
+        None
+    }
+
+    /// Preconfigured http client used to download releases, defaults to `None` (a default client)
+    fn client(&self) -> Option<reqwest::blocking::Client> {
+        None
+    }
+
+    /// Serve the content of `asset` without a network request, e.g. from memory in tests.
+    /// Defaults to `None`, downloading it from its `download_url`
+    fn serve_asset(&self, _asset: &ReleaseAsset) -> Option<Result<Vec<u8>>> {
+        None
+    }
+
+    /// Build a new http client with the same settings as `client`, used where the
+    /// preconfigured client isn't available.
+    /// Defaults to a client built from the `client_options`, if any
+    fn new_client(&self) -> Result<Option<reqwest::blocking::Client>> {
+        self.client_options()
+            .map(|options| options.build_new_client())
+            .transpose()
+    }
+
+    /// Settings of the http clients built where the preconfigured ones aren't available,
+    /// e.g. by `update_async` without an `async_client`. Defaults to `None` (default settings)
+    fn client_options(&self) -> Option<crate::client::ClientOptions> {
+        None
+    }
+
+    /// Async http client used by `update_async`. Defaults to `None`, a client built from
+    /// the `client_options`
+    #[cfg(feature = "async")]
+    fn async_client(&self) -> Option<reqwest::Client> {
+        None
+    }
+
+    /// Async counterpart of `get_latest_release`, used by `update_async`.
+    /// Defaults to calling `get_latest_release`, blocking the task
+    #[cfg(feature = "async")]
+    fn get_latest_release_async(&self) -> AsyncResult<'_, Release> {
+        Box::pin(async move { self.get_latest_release() })
+    }
+
+    /// Async counterpart of `get_release_version`, used by `update_async`.
+    /// Defaults to calling `get_release_version`, blocking the task
+    #[cfg(feature = "async")]
+    fn get_release_version_async<'a>(&'a self, ver: &'a str) -> AsyncResult<'a, Release> {
+        Box::pin(async move { self.get_release_version(ver) })
+    }
+
+    /// Same as `update`, fetching the release and downloading the asset through
+    /// `reqwest`'s async client, see `async_client`.
+    ///
+    /// No confirmation prompt is shown, `no_confirm` must be set. Installing the downloaded
+    /// release, including waiting on `can_apply` and running the hooks, blocks the task.
+    #[cfg(feature = "async")]
+    fn update_async(&self) -> AsyncResult<'_, Status> {
+        Box::pin(crate::async_update::update(self))
+    }
+
     /// Display release information and update the current binary to the latest release, pending
     /// confirmation from the user
     fn update(&self) -> Result<Status> {
         let current_version = self.current_version();
-        self.before_update();
-        let r = self
-            .update_extended()
-            .map(|s| s.into_status(current_version));
-        self.after_update();
-        return r;
+        let status = with_success_marker(self, || self.update_extended())
+            .map(|s| s.into_status(current_version.clone()));
+        if !self.dry_run() {
+            run_outcome_cmd(self, &status);
+            notify_webhook(self, &current_version, &status);
+        }
+        status
     }
 
     /// Same as `update`, but returns `UpdateStatus`.
     fn update_extended(&self) -> Result<UpdateStatus> {
         let current_version = self.current_version();
         info!("Current version:{}", &current_version);
-        let target = self.target();
-
-        let release = match self.target_version() {
-            None => {
-                let release = self.get_latest_release()?;
-
-                if !crate::version::bump_is_greater(&current_version, &release.version)? {
-                    if self.ignore_ver_compare() {
-                        info!(
-                            "Ignore version Compare,target version:{} ",
-                            &release.version
-                        );
-                    } else {
-                        info!("Current version:{} is the latest version", &current_version);
-                        return Ok(crate::update::UpdateStatus::UpToDate);
-                    }
-                }
+        phase!(
+            "phase=check_version event=start current={:?}",
+            current_version
+        );
 
-                release
+        let release = self.resolve_target()?;
+        let newer = is_newer(self, &release);
+        phase!(
+            "phase=check_version event=result current={:?} release={:?} newer={}",
+            current_version,
+            release.version,
+            newer
+        );
+        if self.target_version().is_none() && !newer {
+            if self.ignore_ver_compare() {
+                info!(
+                    "Ignore version Compare,target version:{} ",
+                    &release.version
+                );
+            } else {
+                info!("Current version:{} is the latest version", &current_version);
+                return Ok(crate::update::UpdateStatus::UpToDate(current_version));
             }
-            Some(ref ver) => self.get_release_version(ver)?,
-        };
-        let target_asset: ReleaseAsset;
-        if self.idty_target_platform() {
-            target_asset = release.asset_for(&target).ok_or_else(|| {
-                format_err!(Error::Release, "No asset found for target: `{}`", target)
-            })?;
-        } else {
-            target_asset = release.assets[0].clone();
         }
+        self.install_release(release)
+    }
 
-        let bin_install_path = self.bin_install_path();
-        let bin_name = self.bin_name();
-        let prompt_confirmation = !self.no_confirm();
-        if self.show_output() || prompt_confirmation {
-            println!("\n{} release status:", bin_name);
-            println!("  * Current exe: {:?}", bin_install_path);
-            println!("  * New exe release: {:?}", target_asset.name);
-            println!("  * New exe download url: {:?}", target_asset.download_url);
-            println!("\nThe new release will be downloaded/extracted and the existing binary will be replaced.");
-        }
-        if prompt_confirmation {
-            crate::confirm("Do you want to continue? [Y/n] ")?;
+    /// Resolve the release `update` would install: the `target_version` if set, the latest
+    /// release otherwise. No version comparison is performed and nothing is installed,
+    /// e.g. to display or confirm the concrete version, its `date` and release notes
+    /// (`body`) before calling `update`.
+    fn resolve_target(&self) -> Result<Release> {
+        match self.target_version() {
+            None => self.get_latest_release(),
+            Some(ref ver) => self.get_release_version(ver),
         }
+    }
 
-        let tmp_dir_parent = if cfg!(windows) {
-            env::var_os("TEMP").map(PathBuf::from)
-        } else {
-            bin_install_path.parent().map(PathBuf::from)
-        }
-        .ok_or_else(|| Error::Update("Failed to determine parent dir".into()))?;
-        let tmp_dir = tempfile::Builder::new()
-            .prefix(&format!("{}_download", bin_name))
-            .tempdir_in(tmp_dir_parent)?;
-        let tmp_archive_path = tmp_dir.path().join(&target_asset.name);
-        let mut tmp_archive = std::fs::File::create(&tmp_archive_path)?;
+    /// List the available releases, newest first, e.g. for a version picker. The updater's
+    /// configuration (api url, credentials, client) is used, a release can then be
+    /// installed by setting it as the `target_version`. Not every backend can list its
+    /// releases, the default fails with an `Error::Update`
+    fn get_release_list(&self) -> Result<Vec<Release>> {
+        bail!(
+            Error::Update,
+            "Listing releases isn't supported by this backend"
+        )
+    }
 
-        let mut download = crate::Download::from_url(&target_asset.download_url);
-        info!(
-            "Download version:{} ,url :{:?}",
-            &release.version, &target_asset.download_url
+    /// Check if a release newer than the current version exists, returning it, or `None`
+    /// when up to date. Nothing is confirmed, downloaded or installed and no hook runs,
+    /// e.g. to show an update notification. `target_version` isn't considered.
+    fn check_for_update(&self) -> Result<Option<Release>> {
+        let release = self.get_latest_release()?;
+        let newer = is_newer(self, &release);
+        phase!(
+            "phase=check_version event=result current={:?} release={:?} newer={}",
+            self.current_version(),
+            release.version,
+            newer
         );
-        let mut headers = api_headers(&self.auth_token());
-        headers.insert(header::ACCEPT, "application/octet-stream".parse().unwrap());
-        download.set_headers(headers);
-        download.show_progress(self.show_download_progress());
+        Ok(if newer { Some(release) } else { None })
+    }
+
+    /// Verify the installed binary against the checksum recorded for the current version
+    ///
+    /// A missing binary is reported as not intact, a release without a recorded checksum,
+    /// or with one in an unsupported format, can't be verified and is reported as intact.
+    fn verify(&self) -> Result<VerifyReport> {
+        let version = self.current_version();
+        let release = self.get_release_version(&version)?;
+        let asset = select_asset(self, &release)?;
+        let path = installed_bin_path(self);
+        let (algorithm, expected) = match asset.hash.as_deref().map(crate::checksum::parse_checksum)
+        {
+            Some(Some((algorithm, hex))) => (algorithm, Some(hex)),
+            Some(None) => {
+                warn!(
+                    "Can't verify against unsupported checksum:{}",
+                    asset.hash.as_deref().unwrap_or_default()
+                );
+                (crate::checksum::DigestAlgorithm::Sha256, None)
+            }
+            None => (crate::checksum::DigestAlgorithm::Sha256, None),
+        };
+        let actual = if path.is_file() {
+            Some(crate::checksum::file_digest(&path, algorithm)?)
+        } else {
+            None
+        };
+        let report = VerifyReport {
+            version,
+            path,
+            algorithm: algorithm.to_string(),
+            expected,
+            actual,
+        };
+        info!("Verified installed binary:{:?}", &report);
+        Ok(report)
+    }
 
-        if let Some(ref progress_style) = self.progress_style() {
-            download.set_progress_style(progress_style.clone());
+    /// Ensure the correct, intact binary is installed, meant to be run periodically:
+    ///
+    /// 1. `verify` the installed binary against the checksum recorded for the current version
+    /// 2. if it's missing or corrupt, reinstall the current version (`UpdateStatus::Repaired`)
+    /// 3. otherwise, if `ensure_updates` is set and no `target_version` is pinned, update when
+    ///    a newer release exists (`UpdateStatus::Updated`). `ignore_ver_compare` isn't honored
+    ///    here, so repeated calls are idempotent.
+    ///
+    /// Meant to run unattended, no confirmation prompt is shown regardless of `no_confirm`.
+    fn ensure(&self) -> Result<UpdateStatus> {
+        let report = self.verify()?;
+        if !report.intact() {
+            warn!(
+                "Installed binary is missing or corrupt, reinstalling: {}",
+                &report
+            );
+            let release = self.get_release_version(&report.version)?;
+            return with_success_marker(self, || install(self, release, false)).map(|s| match s {
+                UpdateStatus::Updated(_, release, ..) => UpdateStatus::Repaired(release, report),
+                s => s,
+            });
         }
 
-        download.download_to(&mut tmp_archive)?;
+        if self.ensure_updates() && self.target_version().is_none() {
+            let release = self.get_latest_release()?;
+            if is_newer(self, &release) {
+                return with_success_marker(self, || install(self, release, false));
+            }
+        }
+        Ok(UpdateStatus::UpToDate(self.current_version()))
+    }
 
-        info!("Download file path:{:?}", &tmp_archive_path);
+    /// Arguments the installed binary is run with to report its version, default is
+    /// `--version`
+    fn current_version_args(&self) -> Vec<String> {
+        vec!["--version".to_owned()]
+    }
 
-        if self.all_replce() {
-            let bin_path = self.bin_install_path();
-            crate::Extract::from_source(&tmp_archive_path).extract_dir(&bin_path)?;
-            info!(
-                "Finish replace folder,from:{:?},to:{:?}",
-                &tmp_archive_path, &bin_path
-            );
-        } else {
-            let bin_path_in_archive = self.bin_path_in_archive();
-            crate::Extract::from_source(&tmp_archive_path)
-                .extract_file(&tmp_dir.path(), &bin_path_in_archive)?;
-            let new_exe = tmp_dir.path().join(&bin_path_in_archive);
-
-            println!("Bin file:{:?}", &bin_path_in_archive);
-            // Make executable
-            #[cfg(not(windows))]
-            {
-                let mut permissions = std::fs::metadata(&new_exe)?.permissions();
-                permissions.set_mode(0o755);
-                std::fs::set_permissions(&new_exe, permissions)?;
-            }
+    /// Policy applied when the version reported by the installed binary (see
+    /// `current_version_args`) differs from the version advertised by the server,
+    /// default is `VersionMatch::Off`
+    fn verify_version_match(&self) -> VersionMatch {
+        VersionMatch::Off
+    }
 
-            let tmp_file = tmp_dir.path().join(&format!("__{}_backup", bin_name));
+    /// Run the installed binary with `current_version_args` once installed, rolling back
+    /// the update with an `Error::Verify` if it fails, runs longer than the `hook_timeout`
+    /// or doesn't report the installed version. Stricter than `VersionMatch::Fail`, which
+    /// lets crashes fail the update as they are. Default is `false`
+    fn verify_installed_version(&self) -> bool {
+        false
+    }
 
-            crate::Move::from_source(&new_exe)
-                .replace_using_temp(&tmp_file)
-                .to_dest(&bin_install_path, self.all_replce())?;
-        }
+    /// Checksum manifest, a json object mapping asset names to their SHA-256 checksum.
+    /// Either an url or the name of an asset of the release. When set, the downloaded
+    /// asset must have a matching entry.
+    fn checksum_manifest(&self) -> Option<String> {
+        None
+    }
 
-        Ok(crate::update::UpdateStatus::Updated(release))
+    /// Minisign public key the downloaded assets' signatures are verified with, the base64
+    /// key or the whole `minisign.pub` file. When set, every asset must publish a signature.
+    /// Requires the `signatures` feature.
+    fn verifying_key(&self) -> Option<String> {
+        None
     }
-}
 
-// Print out message based on provided flag and flush the output buffer
-fn print_flush(show_output: bool, msg: &str) -> Result<()> {
-    if show_output {
-        println!("{}", msg);
+    /// Minimum size in bytes of the downloaded file, smaller downloads abort the update
+    /// before anything is replaced, default is 1 (empty downloads are rejected)
+    fn min_download_size(&self) -> u64 {
+        1
     }
-    Ok(())
-}
 
-// Print out message based on provided flag
-fn println(show_output: bool, msg: &str) {
-    if show_output {
-        println!("{}", msg);
+    /// Dir the release is downloaded and extracted in, in a temporary sub dir removed once
+    /// the update succeeded or failed. Defaults to `TEMP` on windows, the parent of
+    /// `bin_install_path` otherwise, keeping the download on the install's filesystem
+    fn tmp_dir(&self) -> Option<PathBuf> {
+        None
     }
-}
 
-// Construct a header with an authorisation entry if an auth token is provided
-fn api_headers(auth_token: &Option<String>) -> header::HeaderMap {
-    let mut headers = header::HeaderMap::new();
+    /// Margin in bytes of free disk space required on top of the download's size in the
+    /// staging and install dirs, checked before it's downloaded. `None` disables the check,
+    /// default is `DEFAULT_FREE_SPACE_MARGIN`
+    fn free_space_margin(&self) -> Option<u64> {
+        Some(DEFAULT_FREE_SPACE_MARGIN)
+    }
 
-    if auth_token.is_some() {
-        headers.insert(
-            header::AUTHORIZATION,
-            (String::from("token ") + &auth_token.clone().unwrap())
-                .parse()
-                .unwrap(),
-        );
-    };
+    /// Predicate polled after the download and before `before_update`, the release is only
+    /// installed once it returns `true`, giving up after the timeout
+    fn can_apply(&self) -> Option<(CanApply, Duration)> {
+        None
+    }
 
-    headers
+    /// Flag indicating if the download is retained for `apply_prefetched` when `can_apply`
+    /// times out, rather than discarded, default is false
+    fn retain_on_timeout(&self) -> bool {
+        false
+    }
+
+    /// Path `bin_install_path` is backed up to before it's replaced, restored when installing
+    /// or verifying the new release fails. Other `bin_install_paths` are backed up next to
+    /// themselves. Defaults to `None`, backing up to `<bin_install_path>.bak`
+    fn backup_path(&self) -> Option<PathBuf> {
+        None
+    }
+
+    /// Flag indicating if the backup is kept after a successful update, default is false
+    fn keep_backup(&self) -> bool {
+        false
+    }
+
+    /// Flag indicating if the running executable is moved aside to `<name>.old` on windows
+    /// when it's one of the install paths, and deleted on the next reboot. Default is false.
+    fn overwrite_self(&self) -> bool {
+        false
+    }
+
+    /// Flag indicating if the release is downloaded in a helper process running as `nobody`,
+    /// handing back only the downloaded file, which is verified by the parent before
+    /// installing it. The helper re-executes the current executable, which must call
+    /// `sandbox::serve_helper` at the start of `main`. Only supported on unix when running
+    /// as root, failing with an `Error::Config` otherwise. Default is false
+    fn sandbox_download(&self) -> bool {
+        false
+    }
+
+    /// Timeout of the confirmation prompt and the answer assumed once it expires,
+    /// default is to wait for an answer indefinitely
+    fn confirm_timeout(&self) -> Option<(Duration, bool)> {
+        None
+    }
+
+    /// Reader and writer the confirmation prompt is shown with, default is stdin/stdout.
+    /// `confirm_timeout` only applies to stdin.
+    fn confirm_prompt(&self) -> Option<ConfirmPrompt> {
+        None
+    }
+
+    /// Paths the release is installed to, all updated together or not at all.
+    /// Default is `bin_install_path` only.
+    fn bin_install_paths(&self) -> Vec<PathBuf> {
+        vec![self.bin_install_path()]
+    }
+
+    /// Additional assets of the release installed along with the binary, e.g. a data file,
+    /// as `(asset name, install path)` pairs. They're installed as downloaded, and are all
+    /// downloaded and verified before anything is replaced. Default is none.
+    fn extra_assets(&self) -> Vec<(String, PathBuf)> {
+        vec![]
+    }
+
+    /// Name of the asset installed when no asset matches the target platform
+    fn fallback_asset(&self) -> Option<String> {
+        None
+    }
+
+    /// Exact name of the asset to install, e.g. when a release bundles several files whose
+    /// names don't encode the target. Takes precedence over the target-based selection,
+    /// the update fails if the release has no asset of that name. Defaults to `None`
+    fn asset_name(&self) -> Option<String> {
+        None
+    }
+
+    /// Flag indicating if `.tar.gz` assets are extracted while downloading, without storing
+    /// the archive, default is false. Requires the `archive-tar` and `compression-flate2`
+    /// features and is ignored with `replace_all`, a `download_transform` or when the asset
+    /// has a checksum, manifest entry or signature to verify.
+    fn stream_extract(&self) -> bool {
+        false
+    }
+
+    /// Transform applied to the downloaded bytes before extraction
+    fn download_transform(&self) -> Option<DownloadTransform> {
+        None
+    }
+
+    /// Normalization applied to the current and the release's version before they're
+    /// compared, defaults to stripping surrounding whitespace and a leading `v`/`V`
+    fn version_normalizer(&self) -> VersionNormalizer {
+        VersionNormalizer::default()
+    }
+
+    /// Flag indicating if the downloaded asset's checksum is checked on the bytes as
+    /// downloaded, rather than after `download_transform`, default is false
+    fn checksum_pre_transform(&self) -> bool {
+        false
+    }
+
+    /// Path of the marker written once an update fully completed, `after_update` included.
+    /// Any prior marker is removed when an update starts.
+    fn success_marker_path(&self) -> Option<PathBuf> {
+        None
+    }
+
+    /// Flag indicating if downloads are verified against their `Content-MD5`/`Digest`
+    /// response headers, default is true
+    fn verify_header_digests(&self) -> bool {
+        true
+    }
+
+    /// Flag indicating if `ensure` updates to a newer release, default is true
+    fn ensure_updates(&self) -> bool {
+        true
+    }
+
+    /// Download and verify the release `update` would install into a staging dir next to
+    /// the install path, returning the staging dir. The running binary isn't touched, the
+    /// release is installed later by `apply_prefetched`, e.g. on the next restart.
+    fn prefetch(&self) -> Result<PathBuf> {
+        let release = self.resolve_target()?;
+        let asset = select_asset(self, &release)?;
+        let extras = extra_release_assets(self, &release)?;
+        let dir = prefetch_dir(self)?;
+        download_asset(self, &release, &asset, &dir, false)?;
+        for extra in &extras {
+            download_asset(self, &release, extra, &dir, false)?;
+        }
+        let extra_names = extras.into_iter().map(|a| a.name).collect::<Vec<_>>();
+        write_prefetch_info(&dir, &release.version, &asset.name, &extra_names)?;
+        Ok(dir)
+    }
+
+    /// Install a release staged by `prefetch`, running the update hooks, then remove
+    /// the staging `dir`. The staged files are checked against the checksums recorded
+    /// when they were verified, failing with an `Error::Checksum` if any changed since.
+    fn apply_prefetched(&self, dir: &Path) -> Result<UpdateStatus> {
+        let info: PrefetchInfo =
+            serde_json::from_reader(std::fs::File::open(dir.join(PREFETCH_INFO))?)?;
+        check_prefetched(dir, &info)?;
+        let staged = StagedAsset {
+            dir: dir.to_owned(),
+            archive: dir.join(&info.asset_name),
+            streamed: false,
+            stats: None,
+        };
+        let release = Release {
+            name: self.bin_name(),
+            version: info.version,
+            assets: vec![ReleaseAsset {
+                name: info.asset_name,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        // probed before the new binary is installed
+        let current_version = self.current_version();
+        let status = with_success_marker(self, || {
+            let hooks = install_with_hooks(self, &staged, &release.version)?;
+            Ok(UpdateStatus::Updated(current_version, release, None, hooks))
+        })?;
+        std::fs::remove_dir_all(dir)?;
+        Ok(status)
+    }
+
+    /// Download and install `release`, replacing the current binary, pending confirmation
+    /// from the user. No version comparison is performed.
+    fn install_release(&self, release: Release) -> Result<UpdateStatus> {
+        install(self, release, !self.no_confirm())
+    }
+}
+
+// Download and install `release`, replacing the current binary, asking the user to confirm
+// first if `prompt_confirmation` is set
+fn install<U: ReleaseUpdate + ?Sized>(
+    updater: &U,
+    release: Release,
+    prompt_confirmation: bool,
+) -> Result<UpdateStatus> {
+    let current_version = updater.current_version();
+    let target_asset = select_asset(updater, &release)?;
+    let extras = extra_release_assets(updater, &release)?;
+
+    let bin_install_path = updater.bin_install_path();
+    let bin_name = updater.bin_name();
+
+    if updater.dry_run() {
+        return plan_update(updater, &release, target_asset);
+    }
+
+    if updater.output_format() == OutputFormat::Json {
+        if updater.show_output() {
+            print_json(&serde_json::json!({
+                "phase": "release",
+                "current_version": current_version,
+                "version": release.version,
+                "asset": target_asset.name,
+                "download_url": target_asset.download_url,
+                "date": release.date,
+                "notes": release.body,
+            }));
+        }
+    } else if updater.show_output() || prompt_confirmation {
+        println!("\n{} release status:", bin_name);
+        println!("  * Current exe: {:?}", bin_install_path);
+        println!("  * New exe release: {:?}", target_asset.name);
+        println!("  * New exe download url: {:?}", target_asset.download_url);
+        if !release.date.is_empty() {
+            println!("  * Released: {}", release.date);
+        }
+        if let Some(ref notes) = release.body {
+            println!("  * Release notes:");
+            for line in notes.lines() {
+                println!("      {}", line);
+            }
+        }
+        println!("\nThe new release will be downloaded/extracted and the existing binary will be replaced.");
+    }
+    if prompt_confirmation {
+        let msg = "Do you want to continue? [Y/n] ";
+        match (updater.confirm_prompt(), updater.confirm_timeout()) {
+            (Some(prompt), _) => prompt.confirm(msg)?,
+            (None, Some((timeout, default_answer))) => {
+                crate::confirm_timeout(msg, timeout, default_answer)?
+            }
+            (None, None) => crate::confirm(msg)?,
+        }
+    }
+
+    let tmp_dir = tempfile::Builder::new()
+        .prefix(&format!("{}_download", bin_name))
+        .tempdir_in(staging_parent(updater)?)?;
+    let staged = download_asset(updater, &release, &target_asset, tmp_dir.path(), true)?;
+    let mut stats = staged.stats;
+    let extra_names = extras.iter().map(|a| a.name.clone()).collect::<Vec<_>>();
+    for extra in extras {
+        let extra = download_asset(updater, &release, &extra, tmp_dir.path(), false)?;
+        stats = add_stats(stats, extra.stats);
+    }
+    wait_can_apply(updater, &release, &staged, &extra_names)?;
+    let hooks = install_with_hooks(updater, &staged, &release.version)?;
+
+    Ok(crate::update::UpdateStatus::Updated(
+        current_version,
+        release,
+        stats,
+        hooks,
+    ))
+}
+
+// Plan installing `asset` of `release`, in dry-run mode
+pub(crate) fn plan_update<U: ReleaseUpdate + ?Sized>(
+    updater: &U,
+    release: &Release,
+    asset: ReleaseAsset,
+) -> Result<UpdateStatus> {
+    let plan = UpdatePlan {
+        bin_name: updater.bin_name(),
+        current_version: updater.current_version(),
+        target_version: release.version.clone(),
+        asset_name: asset.name,
+        asset_url: asset.download_url,
+        checksum: asset.hash,
+        install_path: updater.bin_install_path(),
+        before_cmd: updater.before_cmd(),
+        after_cmd: updater.after_cmd(),
+    };
+    info!("Dry run, planned update:{:?}", &plan);
+    if let Some(plan_path) = updater.dry_run_plan_path() {
+        let plan_file = std::fs::File::create(&plan_path)?;
+        serde_json::to_writer_pretty(plan_file, &plan)?;
+        info!("Dry run plan written to:{:?}", &plan_path);
+    }
+    Ok(UpdateStatus::DryRun(plan))
+}
+
+// Parent dir of the temporary download and staging dirs: the `tmp_dir` if set, `TEMP`
+// on windows, the parent of `bin_install_path` otherwise
+pub(crate) fn staging_parent<U: ReleaseUpdate + ?Sized>(updater: &U) -> Result<PathBuf> {
+    if let Some(dir) = updater.tmp_dir() {
+        return Ok(dir);
+    }
+    if cfg!(windows) {
+        env::var_os("TEMP").map(PathBuf::from)
+    } else {
+        updater.bin_install_path().parent().map(PathBuf::from)
+    }
+    .ok_or_else(|| Error::Update("Failed to determine parent dir".into()))
+}
+
+// A downloaded and verified release asset, ready to be installed
+pub(crate) struct StagedAsset {
+    // dir holding the asset, also used for its extraction
+    pub(crate) dir: PathBuf,
+    // path of the downloaded asset
+    pub(crate) archive: PathBuf,
+    // `bin_path_in_archive` was already extracted into `dir` while downloading
+    pub(crate) streamed: bool,
+    // stats of the download, `None` when the asset wasn't downloaded
+    pub(crate) stats: Option<DownloadStats>,
+}
+
+// Combined stats of the downloads of a release's assets
+pub(crate) fn add_stats(
+    stats: Option<DownloadStats>,
+    more: Option<DownloadStats>,
+) -> Option<DownloadStats> {
+    match (stats, more) {
+        (Some(stats), Some(more)) => Some(stats.combine(more)),
+        (stats, more) => stats.or(more),
+    }
+}
+
+// Download `asset` into `dir`, checking its size and checksums and applying the
+// `download_transform`. The asset may be extracted while downloading if `allow_stream`.
+fn download_asset<U: ReleaseUpdate + ?Sized>(
+    updater: &U,
+    release: &Release,
+    asset: &ReleaseAsset,
+    dir: &Path,
+    allow_stream: bool,
+) -> Result<StagedAsset> {
+    let archive = dir.join(&asset.name);
+    let bin_path_in_archive = updater.bin_path_in_archive();
+
+    let new_download = |client: Option<reqwest::blocking::Client>| -> Result<crate::Download> {
+        let mut download = asset_download(updater, asset)?;
+        download.set_client(match client {
+            Some(client) => client,
+            None => request_client(updater)?,
+        });
+        Ok(download)
+    };
+    info!(
+        "Download version:{} ,url :{:?}",
+        &release.version, &asset.download_url
+    );
+    phase!(
+        "phase=download event=start version={:?} asset={:?}",
+        release.version,
+        asset.name
+    );
+
+    let manifest_hash = match updater.checksum_manifest() {
+        Some(manifest) => Some(fetch_manifest_checksum(
+            updater,
+            release,
+            &manifest,
+            &asset.name,
+        )?),
+        None => None,
+    };
+    let signature = match updater.verifying_key() {
+        Some(_) => Some(fetch_signature(updater, asset)?),
+        None => None,
+    };
+
+    let served = updater.serve_asset(asset);
+    let sandboxed = updater.sandbox_download();
+    let streamed = allow_stream
+        && served.is_none()
+        && !sandboxed
+        && updater.stream_extract()
+        && !updater.replace_all()
+        && updater.download_transform().is_none()
+        && asset.hash.is_none()
+        && manifest_hash.is_none()
+        && signature.is_none()
+        && can_stream_extract(&asset.name);
+    let mut stats = None;
+    if streamed {
+        #[cfg(all(feature = "archive-tar", feature = "compression-flate2"))]
+        {
+            let download = new_download(updater.client())?;
+            download.download_extract_file(dir, &bin_path_in_archive)?;
+            stats = download.stats();
+        }
+        info!(
+            "Extracted {:?} while downloading",
+            dir.join(&bin_path_in_archive)
+        );
+        check_download_size(updater, &dir.join(&bin_path_in_archive))?;
+    } else if let Some(content) = served {
+        std::fs::write(&archive, content?)?;
+        process_download(
+            updater,
+            asset,
+            &archive,
+            manifest_hash.as_deref(),
+            signature.as_deref(),
+        )?;
+    } else if sandboxed {
+        info!("Downloading in a sandboxed helper into:{:?}", archive);
+        let started = std::time::Instant::now();
+        crate::sandbox::download(
+            &asset_download(updater, asset)?,
+            &client_options(updater),
+            &archive,
+        )?;
+        // the download ran in the helper, only its outcome is known
+        stats = Some(DownloadStats::new(
+            std::fs::metadata(&archive)?.len(),
+            started.elapsed(),
+        ));
+        process_download(
+            updater,
+            asset,
+            &archive,
+            manifest_hash.as_deref(),
+            signature.as_deref(),
+        )?;
+    } else {
+        // the partial file outlives the staging dir, so a failed download is resumed
+        let part = partial_download_path(updater, &release.version, &asset.name)?;
+        let download = new_download(updater.client())?;
+        if let Err(e) = download.download_resumable(&part) {
+            // a cancelled download isn't meant to be resumed
+            if let Error::Cancelled = e {
+                let _ = std::fs::remove_file(&part);
+                let _ = std::fs::remove_file(crate::resume_validator_path(&part));
+            }
+            return Err(e);
+        }
+        stats = download.stats();
+        if let Err(e) = process_download(
+            updater,
+            asset,
+            &part,
+            manifest_hash.as_deref(),
+            signature.as_deref(),
+        ) {
+            std::fs::remove_file(&part)?;
+            return Err(e);
+        }
+        std::fs::rename(&part, &archive)?;
+        // only removed once empty, the dir is shared by the updater's partial downloads
+        if let Some(parts_dir) = part.parent() {
+            let _ = std::fs::remove_dir(parts_dir);
+        }
+    }
+
+    let downloaded = if streamed {
+        dir.join(&bin_path_in_archive)
+    } else {
+        archive.clone()
+    };
+    phase!(
+        "phase=download event=finish asset={:?} bytes={} streamed={}",
+        asset.name,
+        std::fs::metadata(&downloaded).map(|m| m.len()).unwrap_or(0),
+        streamed
+    );
+    Ok(StagedAsset {
+        dir: dir.to_owned(),
+        archive,
+        streamed,
+        stats,
+    })
+}
+
+// Assets of `release` named by `extra_assets`, all of which must be present
+pub(crate) fn extra_release_assets<U: ReleaseUpdate + ?Sized>(
+    updater: &U,
+    release: &Release,
+) -> Result<Vec<ReleaseAsset>> {
+    updater
+        .extra_assets()
+        .into_iter()
+        .map(|(name, _)| {
+            release
+                .assets
+                .iter()
+                .find(|asset| asset.name == name)
+                .cloned()
+                .ok_or_else(|| {
+                    format_err!(
+                        Error::Release,
+                        "No asset named `{}` in release: `{}`",
+                        name,
+                        release.version
+                    )
+                })
+        })
+        .collect()
+}
+
+// Download of `asset`, with the updater's headers and progress settings
+pub(crate) fn asset_download<U: ReleaseUpdate + ?Sized>(
+    updater: &U,
+    asset: &ReleaseAsset,
+) -> Result<crate::Download> {
+    let mut download = crate::Download::from_url(&asset.download_url);
+    let mut headers = api_headers(
+        &updater.auth_token(),
+        &updater.auth_scheme(),
+        &updater.headers(),
+    )?;
+    headers
+        .entry(header::ACCEPT)
+        .or_insert(header::HeaderValue::from_static("application/octet-stream"));
+    download.set_headers(headers);
+    let json_output = updater.output_format() == OutputFormat::Json;
+    download.show_progress(updater.show_download_progress() && !json_output);
+    download.force_progress(updater.force_progress());
+    download.verify_header_digests(updater.verify_header_digests());
+    if json_output && updater.show_output() {
+        download.set_progress_reporter(crate::progress::SharedReporter::new(Box::new(
+            crate::progress::JsonReporter::new(),
+        )));
+    }
+
+    if let Some(ref progress_style) = updater.progress_style() {
+        download.set_progress_style(progress_style.clone());
+    }
+    // the sandboxed download runs in a helper process, out of the reporter's reach
+    if let Some(reporter) = updater
+        .progress_reporter()
+        .filter(|_| !updater.sandbox_download())
+    {
+        download.set_progress_reporter(reporter);
+    }
+    if let Some(cancel) = updater.cancel_flag() {
+        download.set_cancel_flag(cancel);
+    }
+    if let Some(margin) = updater.free_space_margin() {
+        let mut dirs = staging_parent(updater).ok().into_iter().collect::<Vec<_>>();
+        if let Some(install_dir) = updater.bin_install_path().parent() {
+            if !dirs.iter().any(|dir| dir == install_dir) {
+                dirs.push(install_dir.to_owned());
+            }
+        }
+        download.require_free_space(dirs, margin);
+    }
+    Ok(download)
+}
+
+// Check the size, signature and checksums of the `asset` downloaded to `archive`, applying
+// the `download_transform`. The signature covers the asset as downloaded.
+pub(crate) fn process_download<U: ReleaseUpdate + ?Sized>(
+    updater: &U,
+    asset: &ReleaseAsset,
+    archive: &Path,
+    manifest_hash: Option<&str>,
+    signature: Option<&str>,
+) -> Result<()> {
+    info!("Download file path:{:?}", archive);
+    check_download_size(updater, archive)?;
+    if let (Some(key), Some(signature)) = (updater.verifying_key(), signature) {
+        crate::signature::verify(&key, &std::fs::read(archive)?, signature)?;
+        info!("Verified download signature of:{:?}", archive);
+        phase!("phase=verify source=signature result=ok");
+    }
+
+    let expected_hash = asset.hash.as_deref();
+    let check_pre_transform = updater.checksum_pre_transform();
+    if check_pre_transform {
+        verify_download(archive, expected_hash, manifest_hash)?;
+    }
+    if let Some(transform) = updater.download_transform() {
+        let data = std::fs::read(archive)?;
+        std::fs::write(archive, transform.apply(&data)?)?;
+        info!("Applied download transform to:{:?}", archive);
+    }
+    if !check_pre_transform {
+        verify_download(archive, expected_hash, manifest_hash)?;
+    }
+    Ok(())
+}
+
+// Install a downloaded asset into every `bin_install_paths`
+//
+// Install paths are made absolute first, so nothing is resolved against the working dir
+// mid-swap. When the working dir is a replaced install dir, the process steps out of it
+// for the swap and back into the new dir afterwards, instead of being left in the
+// removed old dir.
+fn install_staged<U: ReleaseUpdate + ?Sized>(updater: &U, staged: &StagedAsset) -> Result<()> {
+    let bin_path_in_archive = updater.bin_path_in_archive();
+
+    let install_paths = updater
+        .bin_install_paths()
+        .into_iter()
+        .map(std::path::absolute)
+        .collect::<std::io::Result<Vec<_>>>()?;
+    for install_path in &install_paths {
+        move_conflict_aside(updater, install_path)?;
+    }
+
+    if !updater.replace_all() {
+        if !staged.streamed {
+            crate::Extract::from_source(&staged.archive)
+                .extract_file(&staged.dir, &bin_path_in_archive)?;
+        }
+        println(updater, &format!("Bin file:{:?}", &bin_path_in_archive));
+        // Make executable
+        #[cfg(not(windows))]
+        {
+            let new_exe = staged.dir.join(&bin_path_in_archive);
+            let mut permissions = std::fs::metadata(&new_exe)?.permissions();
+            permissions.set_mode(0o755);
+            std::fs::set_permissions(&new_exe, permissions)?;
+        }
+    }
+
+    // stage the new release next to every destination, then swap them all in, so an
+    // interrupted extraction or copy never leaves a half-written install behind
+    let mut swaps = vec![];
+    for install_path in &install_paths {
+        let staged_path = sibling_path(install_path, "update-new");
+        // left over by an interrupted update
+        remove_path(&staged_path);
+        let staging = if updater.replace_all() {
+            stage_dir(&staged.archive, install_path, &staged_path)
+        } else {
+            stage_file(
+                &staged.dir.join(&bin_path_in_archive),
+                &staged_path,
+                install_path,
+            )
+        };
+        swaps.push((staged_path, install_path.clone()));
+        if let Err(e) = staging {
+            remove_staged(&swaps);
+            return Err(e);
+        }
+    }
+    // the extra assets are swapped in along with the binary, downloaded next to it
+    for (name, install_path) in updater.extra_assets() {
+        let install_path = std::path::absolute(install_path)?;
+        let staged_path = sibling_path(&install_path, "update-new");
+        remove_path(&staged_path);
+        let staging = stage_data_file(&staged.dir.join(&name), &staged_path, &install_path);
+        swaps.push((staged_path, install_path));
+        if let Err(e) = staging {
+            remove_staged(&swaps);
+            return Err(e);
+        }
+    }
+    let cwd = env::current_dir().ok();
+    let cwd_install_dir = match cwd {
+        Some(ref cwd) if updater.replace_all() => cwd_install_dir(&install_paths, cwd),
+        _ => None,
+    };
+    if let Some(ref dir) = cwd_install_dir {
+        let parent = dir.parent().unwrap_or(dir);
+        info!(
+            "Working dir:{:?} is replaced, stepping out to:{:?} during the swap",
+            dir, parent
+        );
+        env::set_current_dir(parent)?;
+    }
+    phase!("phase=replace event=start paths={:?}", install_paths);
+    // windows can't replace the running executable, it's moved aside first
+    #[cfg(windows)]
+    let moved_self = if updater.overwrite_self() {
+        move_self_aside(&install_paths)?
+    } else {
+        None
+    };
+    let swapped = swap_all(&swaps);
+    if let Some(ref dir) = cwd_install_dir {
+        env::set_current_dir(dir)?;
+    }
+    #[cfg(windows)]
+    {
+        if let Some((ref exe, ref old)) = moved_self {
+            match swapped {
+                Ok(()) => delete_on_reboot(old),
+                Err(_) => std::fs::rename(old, exe)?,
+            }
+        }
+    }
+    if let Err(e) = swapped {
+        remove_staged(&swaps);
+        phase!("phase=replace event=failed error={:?}", e.to_string());
+        return Err(e);
+    }
+    info!(
+        "Finish replace of install paths:{:?},from:{:?}",
+        &install_paths, &staged.archive
+    );
+    phase!("phase=replace event=finish paths={:?}", install_paths);
+    Ok(())
+}
+
+// Install a downloaded asset between the `before_update` and `after_update` hooks, with
+// the `service_name` stopped, then check the installed version, returning the output of
+// the hooks and service commands. The `on_existing_conflict` policy is checked before
+// anything else is done. The install paths are backed up first and restored if any step
+// fails, nothing is installed when stopping the service or `before_update` fails.
+// The stopped service is started again once installed, or restored.
+pub(crate) fn install_with_hooks<U: ReleaseUpdate + ?Sized>(
+    updater: &U,
+    staged: &StagedAsset,
+    version: &str,
+) -> Result<Vec<HookOutput>> {
+    check_conflicts(updater)?;
+    let backups = backup_install(updater)?;
+    let mut hooks = vec![];
+    let r = install_between_hooks(updater, staged, version, &mut hooks);
+    if let Err(ref e) = r {
+        warn!("Update failed, restoring the backups: {}", e);
+        restore_backups(&backups);
+    }
+    let started = if hooks.iter().any(|h| h.stage == crate::service::STOP_STAGE) {
+        crate::service::start_service(updater).map(|start| hooks.extend(start))
+    } else {
+        Ok(())
+    };
+    match (r, started) {
+        (Ok(()), Ok(())) if !updater.keep_backup() => {
+            for (_, backup) in &backups {
+                remove_path(backup);
+            }
+        }
+        (Ok(()), Ok(())) => info!("Kept backups:{:?}", &backups),
+        (Ok(()), Err(e)) => {
+            warn!("Service failed to start, kept backups:{:?}", &backups);
+            return Err(e);
+        }
+        (Err(e), _) => return Err(e),
+    }
+    Ok(hooks)
+}
+
+// Stop the `service_name`, install `staged` between the `before_update` and `after_update`
+// hooks and check the installed version, collecting the hooks' output
+fn install_between_hooks<U: ReleaseUpdate + ?Sized>(
+    updater: &U,
+    staged: &StagedAsset,
+    version: &str,
+    hooks: &mut Vec<HookOutput>,
+) -> Result<()> {
+    hooks.extend(crate::service::stop_service(updater)?);
+    hooks.extend(updater.before_update()?);
+    let r = install_staged(updater, staged);
+    // run even when the install failed, e.g. to restart a stopped service
+    let after = updater.after_update();
+    r?;
+    hooks.extend(after?);
+    check_version_match(updater, version)
+}
+
+// Copy every existing install path to its backup, returning the `(install_path, backup)` pairs
+fn backup_install<U: ReleaseUpdate + ?Sized>(updater: &U) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let bin_install_path = std::path::absolute(updater.bin_install_path())?;
+    let extra_paths = updater.extra_assets().into_iter().map(|(_, path)| path);
+    let mut backups = vec![];
+    for install_path in updater.bin_install_paths().into_iter().chain(extra_paths) {
+        let install_path = std::path::absolute(install_path)?;
+        if !install_path.exists() {
+            continue;
+        }
+        let backup = match updater.backup_path() {
+            Some(backup) if install_path == bin_install_path => backup,
+            _ => sibling_path(&install_path, "bak"),
+        };
+        // left over by a previous update
+        remove_path(&backup);
+        copy_path(&install_path, &backup).map_err(|e| {
+            format_err!(
+                Error::Update,
+                "Failed to back up {:?} to {:?}: {}",
+                install_path,
+                backup,
+                e
+            )
+        })?;
+        info!("Backed up:{:?} to {:?}", &install_path, &backup);
+        backups.push((install_path, backup));
+    }
+    Ok(backups)
+}
+
+// Restore the install paths from their backups, after a failed update
+fn restore_backups(backups: &[(PathBuf, PathBuf)]) {
+    for (install_path, backup) in backups {
+        remove_path(install_path);
+        match std::fs::rename(backup, install_path) {
+            Ok(()) => info!("Restored:{:?} from {:?}", install_path, backup),
+            Err(e) => error!(
+                "Failed to restore {:?} from {:?}: {}",
+                install_path, backup, e
+            ),
+        }
+    }
+}
+
+// Copy the file or directory `src` to `dest`
+fn copy_path(src: &Path, dest: &Path) -> Result<()> {
+    if src.is_dir() {
+        std::fs::create_dir_all(dest)?;
+        let mut options = fs_extra::dir::CopyOptions::new();
+        options.content_only = true;
+        fs_extra::dir::copy(src, dest, &options)
+            .map_err(|e| format_err!(Error::Update, "{}", e))?;
+    } else {
+        std::fs::copy(src, dest)?;
+    }
+    Ok(())
+}
+
+// Poll `can_apply` until it allows installing the downloaded release. On timeout the
+// download, along with the `extras` assets downloaded next to it, is moved to the prefetch
+// dir if `retain_on_timeout`, discarded otherwise.
+pub(crate) fn wait_can_apply<U: ReleaseUpdate + ?Sized>(
+    updater: &U,
+    release: &Release,
+    staged: &StagedAsset,
+    extras: &[String],
+) -> Result<()> {
+    let (can_apply, timeout) = match updater.can_apply() {
+        Some(can_apply) => can_apply,
+        None => return Ok(()),
+    };
+    let start = std::time::Instant::now();
+    loop {
+        if can_apply.check() {
+            return Ok(());
+        }
+        let elapsed = start.elapsed();
+        if elapsed >= timeout {
+            break;
+        }
+        std::thread::sleep(std::cmp::min(CAN_APPLY_POLL_INTERVAL, timeout - elapsed));
+    }
+    can_apply_timed_out(updater, release, staged, extras, timeout)
+}
+
+// Give up on installing the downloaded release once `can_apply` didn't allow it within
+// `timeout`, retaining the download if `retain_on_timeout`
+pub(crate) fn can_apply_timed_out<U: ReleaseUpdate + ?Sized>(
+    updater: &U,
+    release: &Release,
+    staged: &StagedAsset,
+    extras: &[String],
+    timeout: Duration,
+) -> Result<()> {
+    if updater.retain_on_timeout() && !staged.streamed {
+        let dir = prefetch_dir(updater)?;
+        let asset_name = staged
+            .archive
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        std::fs::rename(&staged.archive, dir.join(&asset_name))?;
+        for name in extras {
+            std::fs::rename(staged.dir.join(name), dir.join(name))?;
+        }
+        write_prefetch_info(&dir, &release.version, &asset_name, extras)?;
+        bail!(
+            Error::Update,
+            "Not allowed to apply version {} within {:?}, download retained in {:?} for `apply_prefetched`",
+            release.version,
+            timeout,
+            dir
+        )
+    }
+    bail!(
+        Error::Update,
+        "Not allowed to apply version {} within {:?}, download discarded",
+        release.version,
+        timeout
+    )
+}
+
+// Interval `can_apply` is polled at
+pub(crate) const CAN_APPLY_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// Partial download file of `asset_name` of `version`, removing the partial files, and
+// their validators, of other releases
+fn partial_download_path<U: ReleaseUpdate + ?Sized>(
+    updater: &U,
+    version: &str,
+    asset_name: &str,
+) -> Result<PathBuf> {
+    let dir = staging_parent(updater)?.join(format!("{}_partial", updater.bin_name()));
+    std::fs::create_dir_all(&dir)?;
+    let name = format!("{}_{}.part", version, asset_name);
+    let validator = crate::resume_validator_path(Path::new(&name));
+    for entry in std::fs::read_dir(&dir)? {
+        let entry = entry?;
+        if entry.file_name() != name.as_str() && Path::new(&entry.file_name()) != validator {
+            std::fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(dir.join(name))
+}
+
+// Empty staging dir of prefetched releases
+fn prefetch_dir<U: ReleaseUpdate + ?Sized>(updater: &U) -> Result<PathBuf> {
+    let dir = staging_parent(updater)?.join(format!("{}_prefetch", updater.bin_name()));
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir)?;
+    }
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+// Describe the prefetched release stored in `dir`, recording the checksums of its
+// verified `asset_name` and `extras` files
+fn write_prefetch_info(
+    dir: &Path,
+    version: &str,
+    asset_name: &str,
+    extras: &[String],
+) -> Result<()> {
+    let mut sha256 = std::collections::BTreeMap::new();
+    for name in std::iter::once(asset_name).chain(extras.iter().map(String::as_str)) {
+        sha256.insert(
+            name.to_owned(),
+            crate::checksum::sha256_file(dir.join(name))?,
+        );
+    }
+    let info = PrefetchInfo {
+        version: version.to_owned(),
+        asset_name: asset_name.to_owned(),
+        sha256,
+    };
+    serde_json::to_writer(std::fs::File::create(dir.join(PREFETCH_INFO))?, &info)?;
+    info!("Prefetched version:{} into:{:?}", version, dir);
+    Ok(())
+}
+
+// Description of a prefetched release, stored in its staging dir
+#[derive(Debug, Deserialize, Serialize)]
+struct PrefetchInfo {
+    version: String,
+    asset_name: String,
+    /// SHA-256 checksums of the staged files, keyed on their name
+    #[serde(default)]
+    sha256: std::collections::BTreeMap<String, String>,
+}
+
+// Check the files staged in `dir` still match the checksums recorded in `info`
+fn check_prefetched(dir: &Path, info: &PrefetchInfo) -> Result<()> {
+    if !info.sha256.contains_key(&info.asset_name) {
+        bail!(
+            Error::Checksum,
+            "No checksum recorded for the prefetched {:?}",
+            dir.join(&info.asset_name)
+        )
+    }
+    for (name, sha256) in &info.sha256 {
+        crate::checksum::verify_sha256_file(dir.join(name), sha256)?;
+    }
+    Ok(())
+}
+
+const PREFETCH_INFO: &str = "prefetch.json";
+
+/// Default `free_space_margin`, 10MiB
+pub const DEFAULT_FREE_SPACE_MARGIN: u64 = 10 * 1024 * 1024;
+
+// Build a command running `cmd` through the platform's shell
+pub(crate) fn shell_command(cmd: &str) -> std::process::Command {
+    if cfg!(target_os = "windows") {
+        let mut command = std::process::Command::new("cmd");
+        command.args(["/C", cmd]);
+        command
+    } else {
+        let mut command = std::process::Command::new("sh");
+        command.arg("-c").arg(cmd);
+        command
+    }
+}
+
+// Run the `before_update` or `after_update` hook command `cmd` through the shell
+fn run_hook<U: ReleaseUpdate + ?Sized>(
+    updater: &U,
+    stage: &str,
+    cmd: Option<String>,
+) -> Result<Option<HookOutput>> {
+    let cmd = match cmd {
+        Some(cmd) => cmd,
+        None => return Ok(None),
+    };
+    let command = shell_command(&cmd);
+    run_hook_command(stage, cmd, command, updater.hook_timeout()).map(Some)
+}
+
+// Run the `command` of a hook, displayed as `cmd`, capturing its output. Fails with an
+// `Error::Update`, carrying the output, when it can't be run, exits non-zero or runs
+// longer than `timeout`.
+pub(crate) fn run_hook_command(
+    stage: &str,
+    cmd: String,
+    command: std::process::Command,
+    timeout: Duration,
+) -> Result<HookOutput> {
+    let output = run_with_timeout(command, timeout).map_err(|e| {
+        phase!(
+            "phase=hook stage={:?} success=false error={:?}",
+            stage,
+            e.to_string()
+        );
+        format_err!(Error::Update, "{} command {:?} {}", stage, cmd, e)
+    })?;
+    let output = HookOutput {
+        stage: stage.to_owned(),
+        cmd,
+        code: output.status.code(),
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+    };
+    info!(
+        "{}:{:?},Status:{:?},Result:{}",
+        stage, output.cmd, output.code, output.stdout
+    );
+    phase!(
+        "phase=hook stage={:?} success={} code={:?}",
+        stage,
+        output.success(),
+        output.code
+    );
+    if !output.success() {
+        bail!(
+            Error::Update,
+            "{} command {:?} failed with code {:?}, stdout: {:?}, stderr: {:?}",
+            stage,
+            output.cmd,
+            output.code,
+            output.stdout.trim(),
+            output.stderr.trim()
+        )
+    }
+    Ok(output)
+}
+
+// Run `command`, collecting its output, killing it after `timeout`
+pub(crate) fn run_with_timeout(
+    mut command: std::process::Command,
+    timeout: Duration,
+) -> Result<std::process::Output> {
+    use std::io::Read;
+    use std::process::Stdio;
+
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format_err!(Error::Update, "failed to run: {}", e))?;
+    // drained while waiting, a full pipe would block the command
+    let drain = |pipe: Option<Box<dyn Read + Send>>| {
+        std::thread::spawn(move || {
+            let mut buf = vec![];
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut buf);
+            }
+            buf
+        })
+    };
+    let stdout = drain(child.stdout.take().map(|p| Box::new(p) as _));
+    let stderr = drain(child.stderr.take().map(|p| Box::new(p) as _));
+
+    let start = std::time::Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if start.elapsed() >= timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!(Error::Update, "timed out after {:?}", timeout)
+        }
+        std::thread::sleep(HOOK_POLL_INTERVAL);
+    };
+    Ok(std::process::Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+// Interval a running hook command is polled at
+const HOOK_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Default `hook_timeout`, 5 minutes
+pub const DEFAULT_HOOK_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+// Run the `on_success_cmd` or `on_failure_cmd` matching the outcome of `update`, killed after
+// the `hook_timeout`. Failures of the command itself are logged, the outcome isn't changed.
+pub(crate) fn run_outcome_cmd<U: ReleaseUpdate + ?Sized>(updater: &U, status: &Result<Status>) {
+    let (cmd, envs) = match status {
+        Ok(status) => {
+            let kind = if status.updated() {
+                "Updated"
+            } else {
+                "UpToDate"
+            };
+            (
+                updater.on_success_cmd(),
+                vec![
+                    ("UPDATE_STATUS", kind.to_owned()),
+                    ("UPDATE_VERSION", status.version().to_owned()),
+                ],
+            )
+        }
+        Err(e) => (
+            updater.on_failure_cmd(),
+            vec![
+                ("UPDATE_ERROR_KIND", e.kind().to_owned()),
+                ("UPDATE_ERROR", e.to_string()),
+            ],
+        ),
+    };
+    let cmd = match cmd {
+        Some(cmd) => cmd,
+        None => return,
+    };
+    let mut command = shell_command(&cmd);
+    command.envs(envs);
+    if let Err(e) = run_hook_command("Outcome", cmd, command, updater.hook_timeout()) {
+        warn!("{}", e);
+    }
+}
+
+// POST the `on_complete_webhook` notification when `update` installed a release.
+// Failures to notify are logged, the outcome isn't changed.
+fn notify_webhook<U: ReleaseUpdate + ?Sized>(
+    updater: &U,
+    old_version: &str,
+    status: &Result<Status>,
+) {
+    let (url, payload) = match webhook_payload(updater, old_version, status) {
+        Some(notification) => notification,
+        None => return,
+    };
+    let client = match request_client(updater) {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("Failed to notify webhook:{:?}, {}", url, e);
+            return;
+        }
+    };
+    let r = client
+        .post(&url)
+        .json(&payload)
+        .send()
+        .and_then(|resp| resp.error_for_status());
+    log_webhook(&url, r.map(|resp| resp.status()));
+}
+
+// The `on_complete_webhook` url and payload, when set and `status` installed a release
+pub(crate) fn webhook_payload<U: ReleaseUpdate + ?Sized>(
+    updater: &U,
+    old_version: &str,
+    status: &Result<Status>,
+) -> Option<(String, serde_json::Value)> {
+    let url = updater.on_complete_webhook()?;
+    let new_version = match status {
+        Ok(status) if status.updated() => status.version(),
+        _ => return None,
+    };
+    let payload = serde_json::json!({
+        "name": updater.bin_name(),
+        "old_version": old_version,
+        "new_version": new_version,
+        "host": hostname(),
+    });
+    Some((url, payload))
+}
+
+// Log the outcome of POSTing the webhook notification to `url`
+pub(crate) fn log_webhook(url: &str, r: reqwest::Result<reqwest::StatusCode>) {
+    match r {
+        Ok(status) => {
+            info!("Notified webhook:{:?},Status:{}", url, status);
+            phase!(
+                "phase=hook stage=\"webhook\" success=true code={}",
+                status.as_u16()
+            );
+        }
+        Err(e) => {
+            warn!("Failed to notify webhook:{:?}, {}", url, e);
+            phase!(
+                "phase=hook stage=\"webhook\" success=false error={:?}",
+                e.to_string()
+            );
+        }
+    }
+}
+
+// Name of the machine, empty when it can't be determined
+fn hostname() -> String {
+    #[cfg(unix)]
+    {
+        let mut buf = [0u8; 256];
+        if unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } == 0 {
+            let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+            return String::from_utf8_lossy(&buf[..len]).into_owned();
+        }
+    }
+    std::env::var("COMPUTERNAME")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .unwrap_or_default()
+}
+
+// Run `f`, writing the success marker once it installed a release, unless in dry-run mode
+fn with_success_marker<U, F>(updater: &U, f: F) -> Result<UpdateStatus>
+where
+    U: ReleaseUpdate + ?Sized,
+    F: FnOnce() -> Result<UpdateStatus>,
+{
+    let marker_path = begin_success_marker(updater)?;
+    let r = f();
+    finish_success_marker(marker_path, &r)?;
+    r
+}
+
+// Remove a stale success marker before updating, returning its path.
+// `None` when unset or in dry-run mode.
+pub(crate) fn begin_success_marker<U: ReleaseUpdate + ?Sized>(
+    updater: &U,
+) -> Result<Option<PathBuf>> {
+    if updater.dry_run() {
+        return Ok(None);
+    }
+    let marker_path = updater.success_marker_path();
+    if let Some(ref marker_path) = marker_path {
+        remove_success_marker(marker_path)?;
+    }
+    Ok(marker_path)
+}
+
+// Write the success marker at `marker_path` once `r` installed a release
+pub(crate) fn finish_success_marker(
+    marker_path: Option<PathBuf>,
+    r: &Result<UpdateStatus>,
+) -> Result<()> {
+    if let (Some(marker_path), Ok(UpdateStatus::Updated(_, release, ..)))
+    | (Some(marker_path), Ok(UpdateStatus::Repaired(release, _))) = (marker_path, r)
+    {
+        write_success_marker(&marker_path, &release.version)?;
+    }
+    Ok(())
+}
+
+// Check the downloaded file isn't smaller than `min_download_size`
+fn check_download_size<U: ReleaseUpdate + ?Sized>(updater: &U, path: &Path) -> Result<()> {
+    let download_size = std::fs::metadata(path)?.len();
+    let min_download_size = updater.min_download_size();
+    if download_size < min_download_size {
+        bail!(
+            Error::Update,
+            "Downloaded file is {} bytes, smaller than the minimum of {} bytes",
+            download_size,
+            min_download_size
+        )
+    }
+    Ok(())
+}
+
+// Check if the asset `name` is a `.tar.gz` archive which can be extracted while downloading
+fn can_stream_extract(name: &str) -> bool {
+    #[cfg(all(feature = "archive-tar", feature = "compression-flate2"))]
+    {
+        matches!(
+            crate::detect_archive(Path::new(name)),
+            Ok(crate::ArchiveKind::Tar(Some(crate::Compression::Gz)))
+        )
+    }
+    #[cfg(not(all(feature = "archive-tar", feature = "compression-flate2")))]
+    {
+        let _ = name;
+        false
+    }
+}
+
+// Verify the downloaded asset against the checksum recorded by the backend and the
+// checksum manifest's entry, if any
+fn verify_download(path: &Path, expected: Option<&str>, manifest: Option<&str>) -> Result<()> {
+    match expected {
+        Some(expected) if crate::checksum::parse_checksum(expected).is_some() => {
+            verify_checksum(path, expected, "asset")?;
+            info!("Verified download checksum:{}", expected);
+        }
+        Some(expected) => {
+            warn!("Skipping verification of unsupported checksum:{}", expected);
+            phase!("phase=verify source=asset result=skipped");
+        }
+        None => {}
+    }
+    if let Some(manifest) = manifest {
+        verify_checksum(path, manifest, "manifest")?;
+        info!("Verified download checksum from manifest:{}", manifest);
+    }
+    Ok(())
+}
+
+// Verify `path` against the `expected` checksum from `source`, logging the result
+fn verify_checksum(path: &Path, expected: &str, source: &str) -> Result<()> {
+    let r = crate::checksum::verify_file(path, expected);
+    phase!(
+        "phase=verify source={} result={}",
+        source,
+        if r.is_ok() { "ok" } else { "mismatch" }
+    );
+    r
+}
+
+// Http client of the backend's requests: its `client`, or a new client with its settings
+// (`new_client`), a client with default settings and its pin only when it has neither
+fn request_client<U: ReleaseUpdate + ?Sized>(updater: &U) -> Result<reqwest::blocking::Client> {
+    if let Some(client) = updater.client() {
+        return Ok(client);
+    }
+    match updater.new_client()? {
+        Some(client) => Ok(client),
+        None => client_options(updater).build_new_client(),
+    }
+}
+
+// The updater's `client_options`, default ones with its `pinned_cert_sha256` otherwise
+pub(crate) fn client_options<U: ReleaseUpdate + ?Sized>(updater: &U) -> ClientOptions {
+    updater.client_options().unwrap_or_else(|| ClientOptions {
+        pinned_cert_sha256: updater.pinned_cert_sha256(),
+        ..Default::default()
+    })
+}
+
+// Fetch the checksum manifest, an url or the name of an asset of `release`, and return
+// its entry for `asset_name`
+fn fetch_manifest_checksum<U: ReleaseUpdate + ?Sized>(
+    updater: &U,
+    release: &Release,
+    manifest: &str,
+    asset_name: &str,
+) -> Result<String> {
+    let url = manifest_url(release, manifest)?;
+    let resp = request_client(updater)?
+        .get(&url)
+        .headers(api_headers(
+            &updater.auth_token(),
+            &updater.auth_scheme(),
+            &updater.headers(),
+        )?)
+        .send()?;
+    if !resp.status().is_success() {
+        return Err(Error::Status(resp.status(), url));
+    }
+    manifest_entry(&resp.text()?, asset_name, &url)
+}
+
+// Fetch the detached signature of `asset`
+fn fetch_signature<U: ReleaseUpdate + ?Sized>(updater: &U, asset: &ReleaseAsset) -> Result<String> {
+    let url = signature_url(asset)?;
+    let resp = request_client(updater)?
+        .get(&url)
+        .headers(api_headers(
+            &updater.auth_token(),
+            &updater.auth_scheme(),
+            &updater.headers(),
+        )?)
+        .send()?;
+    if !resp.status().is_success() {
+        return Err(Error::Status(resp.status(), url));
+    }
+    Ok(resp.text()?)
+}
+
+// Url of the signature of `asset`, required once a verifying key is set
+pub(crate) fn signature_url(asset: &ReleaseAsset) -> Result<String> {
+    asset.signature_url.clone().ok_or_else(|| {
+        format_err!(
+            Error::Signature,
+            "No signature published for asset: `{}`",
+            asset.name
+        )
+    })
+}
+
+// Url of the checksum manifest, an url or the name of an asset of `release`
+pub(crate) fn manifest_url(release: &Release, manifest: &str) -> Result<String> {
+    if manifest.starts_with("http://") || manifest.starts_with("https://") {
+        return Ok(manifest.to_owned());
+    }
+    release
+        .assets
+        .iter()
+        .find(|asset| asset.name == manifest)
+        .map(|asset| asset.download_url.clone())
+        .ok_or_else(|| {
+            format_err!(
+                Error::Release,
+                "Checksum manifest `{}` not found in release: `{}`",
+                manifest,
+                release.version
+            )
+        })
+}
+
+// Entry for `asset_name` of the checksum manifest served at `url`
+pub(crate) fn manifest_entry(body: &str, asset_name: &str, url: &str) -> Result<String> {
+    let checksums = crate::checksum::parse_manifest(body)?;
+    checksums.get(asset_name).cloned().ok_or_else(|| {
+        format_err!(
+            Error::Update,
+            "No checksum for `{}` in the checksum manifest: {:?}",
+            asset_name,
+            url
+        )
+    })
+}
+
+// Check the installed binary reports the version advertised by the server, per the
+// `verify_version_match` policy, or strictly with `verify_installed_version`. The binary
+// is killed after the `hook_timeout`.
+fn check_version_match<U: ReleaseUpdate + ?Sized>(updater: &U, expected: &str) -> Result<()> {
+    let policy = updater.verify_version_match();
+    let strict = updater.verify_installed_version();
+    if policy == VersionMatch::Off && !strict {
+        return Ok(());
+    }
+    let bin_path = installed_bin_path(updater);
+    let args = updater.current_version_args();
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    let actual = match installed_version(&bin_path, &args, updater.hook_timeout(), strict) {
+        Ok(actual) => actual,
+        Err(e) if strict || policy == VersionMatch::Fail => return Err(e),
+        Err(e) => {
+            warn!("Failed to check the installed version: {}", e);
+            return Ok(());
+        }
+    };
+    let normalizer = updater.version_normalizer();
+    if normalizer.apply(&actual) == normalizer.apply(expected) {
+        return Ok(());
+    }
+    let msg = format!(
+        "Installed binary {:?} reports version {}, but the server advertised {}",
+        bin_path, actual, expected
+    );
+    if strict {
+        bail!(Error::Verify, "{}", msg)
+    }
+    match policy {
+        VersionMatch::Fail => bail!(Error::Update, "{}", msg),
+        _ => warn!("{}", msg),
+    }
+    Ok(())
+}
+
+// Run the installed binary `bin_path` with `args`, killed after `timeout`, returning the
+// version it reports. Fails with `Error::Verify` if it can't run, times out or reports
+// none, and if it exits unsuccessfully when `check_status` is set.
+fn installed_version(
+    bin_path: &Path,
+    args: &[&str],
+    timeout: Duration,
+    check_status: bool,
+) -> Result<String> {
+    let command = format!("`{:?} {}`", bin_path, args.join(" "));
+    let mut cmd = std::process::Command::new(bin_path);
+    cmd.args(args);
+    let output = run_with_timeout(cmd, timeout)
+        .map_err(|e| format_err!(Error::Verify, "{} {}", command, e))?;
+    if check_status && !output.status.success() {
+        bail!(
+            Error::Verify,
+            "{} failed with {}: {}",
+            command,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+    }
+    let msg = crate::version::output_text(&output);
+    crate::version::extract_version(&msg).ok_or_else(|| {
+        format_err!(
+            Error::Verify,
+            "No version found in {} output: {:?}",
+            command,
+            msg.trim()
+        )
+    })
+}
+
+// Remove the marker left by a previous update, if any
+fn remove_success_marker(marker_path: &Path) -> Result<()> {
+    match std::fs::remove_file(marker_path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+// Write the marker signaling `version` was installed
+fn write_success_marker(marker_path: &Path, version: &str) -> Result<()> {
+    let marker = SuccessMarker {
+        version: version.to_owned(),
+        timestamp: unix_timestamp(),
+    };
+    let marker_file = std::fs::File::create(marker_path)?;
+    serde_json::to_writer(marker_file, &marker)?;
+    info!("Success marker written to:{:?}", marker_path);
+    Ok(())
+}
+
+// Seconds since the unix epoch
+fn unix_timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Check if `release` is newer than the current version
+pub(crate) fn is_newer<U: ReleaseUpdate + ?Sized>(updater: &U, release: &Release) -> bool {
+    let normalizer = updater.version_normalizer();
+    newer_version(
+        &normalizer.apply(&updater.current_version()),
+        &normalizer.apply(&release.version),
+        updater.compare_build_metadata(),
+    )
+}
+
+// Compare normalized versions as semver, `release_version` is newer when strictly greater.
+// Versions which aren't valid semver are only compared for inequality.
+fn newer_version(current_version: &str, release_version: &str, compare_build: bool) -> bool {
+    let newer = if compare_build {
+        crate::version::bump_is_greater_build(current_version, release_version)
+    } else {
+        crate::version::bump_is_greater(current_version, release_version)
+    };
+    newer.unwrap_or_else(|e| {
+        warn!(
+            "Failed to compare current version:{:?} and release version:{:?} as semver ({}), updating if they differ",
+            current_version, release_version, e
+        );
+        current_version != release_version
+    })
+}
+
+// Select the asset of `release` to install, matching the target platform if requested
+pub(crate) fn select_asset<U: ReleaseUpdate + ?Sized>(
+    updater: &U,
+    release: &Release,
+) -> Result<ReleaseAsset> {
+    let names = || {
+        release
+            .assets
+            .iter()
+            .map(|asset| asset.name.as_str())
+            .collect::<Vec<_>>()
+    };
+    if let Some(name) = updater.asset_name() {
+        return match release.assets.iter().find(|asset| asset.name == name) {
+            Some(asset) => Ok(asset.clone()),
+            None => bail!(
+                Error::Release,
+                "No asset named: `{}` in release: `{}`, available assets: {:?}",
+                name,
+                release.version,
+                names()
+            ),
+        };
+    }
+    if updater.idty_target_platform() {
+        let target = updater.target();
+        if let Some(asset) = release.asset_for(&target) {
+            return Ok(asset);
+        }
+        if let Some(fallback) = updater.fallback_asset() {
+            if let Some(asset) = release.assets.iter().find(|asset| asset.name == fallback) {
+                info!(
+                    "No asset found for target:{}, using fallback asset:{}",
+                    &target, &fallback
+                );
+                return Ok(asset.clone());
+            }
+        }
+        bail!(
+            Error::Release,
+            "No asset found for target: `{}` in release: `{}`, available assets: {:?}",
+            target,
+            release.version,
+            names()
+        )
+    } else {
+        // a release with builds for several platforms still gets the target's build
+        let target_asset = if release.assets.len() > 1 {
+            release.asset_for(&updater.target())
+        } else {
+            None
+        };
+        target_asset
+            .or_else(|| release.assets.first().cloned())
+            .ok_or_else(|| {
+                format_err!(
+                    Error::Release,
+                    "No asset found in release: `{}`",
+                    release.version
+                )
+            })
+    }
+}
+
+// Path of the installed binary, `bin_name` inside `bin_install_path` when the whole
+// install dir is replaced
+pub(crate) fn installed_bin_path<U: ReleaseUpdate + ?Sized>(updater: &U) -> PathBuf {
+    if updater.replace_all() {
+        updater.bin_install_path().join(updater.bin_name())
+    } else {
+        updater.bin_install_path()
+    }
+}
+
+// Check the `on_existing_conflict` policy allows replacing every install path that
+// doesn't look like the binary being updated, without changing anything yet
+fn check_conflicts<U: ReleaseUpdate + ?Sized>(updater: &U) -> Result<()> {
+    let bin_name = updater.bin_name();
+    for install_path in updater.bin_install_paths() {
+        if !install_conflict(&install_path, &bin_name, updater.replace_all()) {
+            continue;
+        }
+        match updater.on_existing_conflict() {
+            ConflictPolicy::Overwrite => {
+                warn!(
+                    "Install path:{:?} doesn't contain `{}`, overwriting",
+                    install_path, &bin_name
+                );
+            }
+            ConflictPolicy::Fail => bail!(
+                Error::Update,
+                "Install path {:?} already exists and doesn't contain `{}`, refusing to replace it",
+                install_path,
+                bin_name
+            ),
+            ConflictPolicy::Backup => {}
+        }
+    }
+    Ok(())
+}
+
+// Move `install_path` aside with `ConflictPolicy::Backup` if it doesn't look like the
+// binary being updated
+fn move_conflict_aside<U: ReleaseUpdate + ?Sized>(updater: &U, install_path: &Path) -> Result<()> {
+    let bin_name = updater.bin_name();
+    if updater.on_existing_conflict() != ConflictPolicy::Backup
+        || !install_conflict(install_path, &bin_name, updater.replace_all())
+    {
+        return Ok(());
+    }
+    let backup = conflict_backup_path(install_path);
+    std::fs::rename(install_path, &backup)?;
+    info!(
+        "Moved conflicting install path:{:?} to {:?}",
+        install_path, &backup
+    );
+    Ok(())
+}
+
+// Path next to `path`, named after it with the given `suffix`
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path
+        .file_name()
+        .map(|n| n.to_os_string())
+        .unwrap_or_default();
+    name.push(format!(".{}", suffix));
+    path.with_file_name(name)
+}
+
+// Stage the install dir `install_path` updated with the `archive` contents into `staged`
+fn stage_dir(archive: &Path, install_path: &Path, staged: &Path) -> Result<()> {
+    std::fs::create_dir_all(staged)?;
+    if install_path.is_dir() {
+        let mut options = fs_extra::dir::CopyOptions::new();
+        options.content_only = true;
+        options.overwrite = true;
+        fs_extra::dir::copy(install_path, staged, &options)
+            .map_err(|e| format_err!(Error::Update, "Failed to stage {:?}: {}", install_path, e))?;
+    }
+    crate::Extract::from_source(archive).extract_dir(staged)
+}
+
+// Move the running executable, if it's one of the `install_paths`, aside to `<name>.old`,
+// returning its path and the path it was moved to
+#[cfg(windows)]
+fn move_self_aside(install_paths: &[PathBuf]) -> Result<Option<(PathBuf, PathBuf)>> {
+    let current = match env::current_exe().and_then(|exe| exe.canonicalize()) {
+        Ok(current) => current,
+        Err(_) => return Ok(None),
+    };
+    let exe = match install_paths
+        .iter()
+        .find(|path| path.canonicalize().ok().as_ref() == Some(&current))
+    {
+        Some(exe) => exe.clone(),
+        None => return Ok(None),
+    };
+    let old = sibling_path(&exe, "old");
+    // left over by an earlier update, unless it's still pending deletion
+    remove_path(&old);
+    std::fs::rename(&exe, &old)?;
+    info!("Moved the running executable:{:?} aside to:{:?}", exe, old);
+    Ok(Some((exe, old)))
+}
+
+// Schedule the deletion of `path` on the next reboot, as the running executable can't be
+// deleted before it exits. Scheduling requires admin rights, a warning is logged otherwise.
+#[cfg(windows)]
+fn delete_on_reboot(path: &Path) {
+    use std::os::windows::ffi::OsStrExt;
+
+    const MOVEFILE_DELAY_UNTIL_REBOOT: u32 = 0x4;
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn MoveFileExW(existing: *const u16, new: *const u16, flags: u32) -> i32;
+    }
+
+    let wide = path
+        .as_os_str()
+        .encode_wide()
+        .chain(Some(0))
+        .collect::<Vec<u16>>();
+    let scheduled =
+        unsafe { MoveFileExW(wide.as_ptr(), std::ptr::null(), MOVEFILE_DELAY_UNTIL_REBOOT) };
+    if scheduled == 0 {
+        warn!(
+            "Failed to schedule the deletion of {:?} on reboot: {}",
+            path,
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+// Copy the new binary `new_exe` to `staged`, flushed to disk before it's swapped in
+// over `install_path`. On unix the mode of the replaced binary is kept, made at least
+// `0o755`, along with its owner when running as root.
+fn stage_file(new_exe: &Path, staged: &Path, install_path: &Path) -> Result<()> {
+    std::fs::copy(new_exe, staged)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        let mode = match std::fs::metadata(install_path) {
+            Ok(metadata) => {
+                // chown clears the setuid/setgid bits, the mode is applied after it
+                if unsafe { libc::geteuid() } == 0 {
+                    std::os::unix::fs::chown(staged, Some(metadata.uid()), Some(metadata.gid()))?;
+                }
+                metadata.mode() & 0o7777 | 0o755
+            }
+            Err(_) => 0o755,
+        };
+        std::fs::set_permissions(staged, std::fs::Permissions::from_mode(mode))?;
+    }
+    #[cfg(not(unix))]
+    let _ = install_path;
+    std::fs::File::open(staged)?.sync_all()?;
+    Ok(())
+}
+
+// Copy the downloaded extra asset `new_file` to `staged`, with the mode of the
+// `install_path` it replaces when there's one
+fn stage_data_file(new_file: &Path, staged: &Path, install_path: &Path) -> Result<()> {
+    std::fs::copy(new_file, staged)?;
+    if let Ok(metadata) = std::fs::metadata(install_path) {
+        std::fs::set_permissions(staged, metadata.permissions())?;
+    }
+    std::fs::File::open(staged)?.sync_all()?;
+    Ok(())
+}
+
+// Remove a file or directory, ignoring errors
+fn remove_path(path: &Path) {
+    let _ = if path.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    };
+}
+
+// Remove the staged paths left by a failed multi-destination install
+fn remove_staged(swaps: &[(PathBuf, PathBuf)]) {
+    for (staged, _) in swaps {
+        remove_path(staged);
+    }
+}
+
+// The install dir among `install_paths` which is the working dir `cwd`, if any
+fn cwd_install_dir(install_paths: &[PathBuf], cwd: &Path) -> Option<PathBuf> {
+    let cwd = cwd.canonicalize().ok()?;
+    install_paths
+        .iter()
+        .find(|path| path.canonicalize().ok().as_ref() == Some(&cwd))
+        .cloned()
+}
+
+// Swap every staged path into its destination, all or nothing: if any swap fails, the
+// destinations swapped so far are restored from their backups
+fn swap_all(swaps: &[(PathBuf, PathBuf)]) -> Result<()> {
+    let mut swapped: Vec<(&Path, Option<PathBuf>)> = vec![];
+    let mut result = Ok(());
+    for (staged, dest) in swaps {
+        let backup = if dest.exists() {
+            let backup = sibling_path(dest, "update-old");
+            // left over by an interrupted update
+            remove_path(&backup);
+            // a file is kept through a hard link, so the staged file is renamed over it
+            // atomically and `dest` is never missing or half-written. Directories, and
+            // running executables on windows, which can't be replaced, are moved aside.
+            let linked =
+                cfg!(not(windows)) && dest.is_file() && std::fs::hard_link(dest, &backup).is_ok();
+            if !linked {
+                if let Err(e) = std::fs::rename(dest, &backup) {
+                    result = Err(e);
+                    break;
+                }
+            }
+            Some(backup)
+        } else {
+            None
+        };
+        if let Err(e) = std::fs::rename(staged, dest) {
+            if let Some(ref backup) = backup {
+                let _ = std::fs::rename(backup, dest);
+            }
+            result = Err(e);
+            break;
+        }
+        swapped.push((dest, backup));
+    }
+
+    if let Err(e) = result {
+        for (dest, backup) in swapped.into_iter().rev() {
+            remove_path(dest);
+            if let Some(backup) = backup {
+                if let Err(e) = std::fs::rename(&backup, dest) {
+                    error!("Failed to restore {:?} from {:?}: {}", dest, backup, e);
+                }
+            }
+        }
+        bail!(
+            Error::Update,
+            "Failed to replace all install paths, rolled back: {}",
+            e
+        )
+    }
+    for (_, backup) in swapped {
+        if let Some(backup) = backup {
+            remove_path(&backup);
+        }
+    }
+    Ok(())
+}
+
+// Check if the install path exists but doesn't look like the binary being updated.
+// When replacing a whole directory, the directory must contain `bin_name`,
+// otherwise the install path itself must be a file named `bin_name`.
+fn install_conflict(install_path: &Path, bin_name: &str, replace_all: bool) -> bool {
+    if !install_path.exists() {
+        return false;
+    }
+    if replace_all {
+        let is_empty = std::fs::read_dir(install_path)
+            .map(|mut entries| entries.next().is_none())
+            .unwrap_or(false);
+        !is_empty && !install_path.join(bin_name).exists()
+    } else {
+        !install_path.is_file() || install_path.file_name() != Some(std::ffi::OsStr::new(bin_name))
+    }
+}
+
+// Path the conflicting install path is moved to with `ConflictPolicy::Backup`
+fn conflict_backup_path(install_path: &Path) -> PathBuf {
+    let timestamp = unix_timestamp();
+    let mut name = install_path
+        .file_name()
+        .map(|n| n.to_os_string())
+        .unwrap_or_default();
+    name.push(format!(".conflict.{}", timestamp));
+    install_path.with_file_name(name)
+}
+
+// Print out message if `show_output` is set, as a JSON event in `OutputFormat::Json`
+fn println<U: ReleaseUpdate + ?Sized>(updater: &U, msg: &str) {
+    if !updater.show_output() {
+        return;
+    }
+    match updater.output_format() {
+        OutputFormat::Json => print_json(&serde_json::json!({
+            "phase": "message",
+            "message": msg,
+        })),
+        OutputFormat::Human => println!("{}", msg),
+    }
+}
+
+// Print `value` as a line of JSON on stdout
+fn print_json(value: &serde_json::Value) {
+    crate::progress::write_json_line(&mut std::io::stdout().lock(), value);
+}
+
+// Default headers of api requests, with the `auth_token` sent with `scheme` and the
+// caller-supplied `extra` headers, which override the defaults
+pub(crate) fn api_headers(
+    auth_token: &Option<String>,
+    scheme: &AuthScheme,
+    extra: &header::HeaderMap,
+) -> Result<header::HeaderMap> {
+    let mut headers = header::HeaderMap::new();
+    headers.insert(
+        header::USER_AGENT,
+        header::HeaderValue::from_static("rust-reqwest/self-update"),
+    );
+
+    if let Some(value) = scheme.header_value(auth_token.as_deref()) {
+        headers.insert(
+            header::AUTHORIZATION,
+            value
+                .parse()
+                .map_err(|err| Error::Config(format!("Failed to parse auth token: {}", err)))?,
+        );
+    };
+    headers.extend(extra.clone());
+
+    Ok(headers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, File};
+
+    #[test]
+    fn detect_install_conflict() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("self_update_install_conflict")
+            .tempdir()
+            .expect("tempdir fail");
+        let dir = tmp_dir.path();
+
+        assert!(!install_conflict(&dir.join("app"), "app", false));
+        File::create(dir.join("app")).expect("create fail");
+        assert!(!install_conflict(&dir.join("app"), "app", false));
+        File::create(dir.join("other")).expect("create fail");
+        assert!(install_conflict(&dir.join("other"), "app", false));
+        assert!(install_conflict(dir, "app", false));
+
+        let install_dir = dir.join("install");
+        fs::create_dir(&install_dir).expect("create dir fail");
+        assert!(!install_conflict(&install_dir, "app", true));
+        File::create(install_dir.join("unrelated")).expect("create fail");
+        assert!(install_conflict(&install_dir, "app", true));
+        File::create(install_dir.join("app")).expect("create fail");
+        assert!(!install_conflict(&install_dir, "app", true));
+    }
+
+    #[test]
+    fn verify_report_intact() {
+        let report = |expected: Option<&str>, actual: Option<&str>| VerifyReport {
+            version: "1.0.0".into(),
+            path: PathBuf::from("app"),
+            algorithm: "sha256".into(),
+            expected: expected.map(String::from),
+            actual: actual.map(String::from),
+        };
+        assert!(report(Some("abc"), Some("abc")).intact());
+        assert!(report(Some("ABC"), Some("abc")).intact());
+        assert!(report(None, Some("abc")).intact());
+        assert!(!report(Some("abc"), Some("def")).intact());
+        assert!(!report(Some("abc"), None).intact());
+        assert!(!report(None, None).intact());
+        assert_eq!(
+            report(Some("abc"), None).to_string(),
+            "\"app\" version 1.0.0 (sha256): expected abc, found <missing>"
+        );
+    }
+
+    #[test]
+    fn success_marker() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("self_update_success_marker")
+            .tempdir()
+            .expect("tempdir fail");
+        let marker_path = tmp_dir.path().join("updated.json");
+
+        remove_success_marker(&marker_path).expect("remove missing marker fail");
+        write_success_marker(&marker_path, "1.2.3").expect("write marker fail");
+        let marker: serde_json::Value =
+            serde_json::from_reader(File::open(&marker_path).unwrap()).unwrap();
+        assert_eq!("1.2.3", marker["version"]);
+        assert!(marker["timestamp"].as_u64().unwrap() > 0);
+        remove_success_marker(&marker_path).expect("remove marker fail");
+        assert!(!marker_path.exists());
+    }
+
+    #[test]
+    fn release_update_is_thread_safe() {
+        fn assert_send_sync<T: Send + Sync + ?Sized>() {}
+        assert_send_sync::<Box<dyn ReleaseUpdate>>();
+        assert_send_sync::<crate::backends::cloud::Update>();
+    }
+
+    #[test]
+    fn release_summary() {
+        let mut release = Release {
+            name: "app".into(),
+            version: "1.3.0".into(),
+            ..Default::default()
+        };
+        assert_eq!(release.summary_from("1.2.5"), "1.2.5 -> 1.3.0 (minor)");
+        assert_eq!(release.summary_from("1.4.0"), "1.4.0 -> 1.3.0 (downgrade)");
+        assert_eq!(release.summary_from("v1"), "v1 -> 1.3.0");
+
+        release.date = "2021-03-01".into();
+        release.body = Some("\nFaster downloads\nMore details".into());
+        release.assets.push(ReleaseAsset {
+            size: Some(1024),
+            ..Default::default()
+        });
+        assert_eq!(
+            release.summary_from("1.3.0-beta.1"),
+            "1.3.0-beta.1 -> 1.3.0 (pre-release), released 2021-03-01, 1.00KB: Faster downloads"
+        );
+    }
+
+    #[test]
+    fn display_status_and_release() {
+        let mut release = Release {
+            name: "Agent".into(),
+            version: "9.9.10".into(),
+            ..Default::default()
+        };
+        assert_eq!(release.to_string(), "Agent 9.9.10");
+        release.date = "2024-01-15".into();
+        assert_eq!(release.to_string(), "Agent 9.9.10 (2024-01-15)");
+
+        assert_eq!(
+            UpdateStatus::UpToDate("9.9.10".into()).to_string(),
+            "already up to date (9.9.10)"
+        );
+        assert_eq!(
+            UpdateStatus::Updated("9.9.9".into(), release, None, vec![]).to_string(),
+            "updated 9.9.9 → 9.9.10"
+        );
+    }
+
+    #[test]
+    fn no_asset_for_target() {
+        struct Updater(Option<String>, Option<String>);
+        impl ReleaseUpdate for Updater {
+            fn get_latest_release(&self) -> Result<Release> {
+                unimplemented!()
+            }
+            fn get_release_version(&self, _: &str) -> Result<Release> {
+                unimplemented!()
+            }
+            fn current_version(&self) -> String {
+                "1.0.0".into()
+            }
+            fn target(&self) -> String {
+                "x86_64-unknown-linux-gnu".into()
+            }
+            fn target_version(&self) -> Option<String> {
+                None
+            }
+            fn bin_name(&self) -> String {
+                "app".into()
+            }
+            fn bin_install_path(&self) -> PathBuf {
+                PathBuf::from("app")
+            }
+            fn bin_path_in_archive(&self) -> PathBuf {
+                PathBuf::from("app")
+            }
+            fn show_download_progress(&self) -> bool {
+                false
+            }
+            fn show_output(&self) -> bool {
+                false
+            }
+            fn no_confirm(&self) -> bool {
+                true
+            }
+            fn progress_style(&self) -> Option<ProgressStyle> {
+                None
+            }
+            fn auth_token(&self) -> Option<String> {
+                None
+            }
+            fn fallback_asset(&self) -> Option<String> {
+                self.0.clone()
+            }
+            fn asset_name(&self) -> Option<String> {
+                self.1.clone()
+            }
+        }
+
+        let asset = |name: &str| ReleaseAsset {
+            name: name.into(),
+            ..Default::default()
+        };
+        let release = Release {
+            version: "1.1.0".into(),
+            assets: vec![asset("app-windows.zip"), asset("app.tar.gz")],
+            ..Default::default()
+        };
+        let msg = select_asset(&Updater(None, None), &release)
+            .unwrap_err()
+            .to_string();
+        assert!(msg.contains("x86_64-unknown-linux-gnu"));
+        assert!(msg.contains("app-windows.zip"));
+        assert!(msg.contains("app.tar.gz"));
+
+        let fallback = select_asset(&Updater(Some("app.tar.gz".into()), None), &release).unwrap();
+        assert_eq!(fallback.name, "app.tar.gz");
+
+        let named = select_asset(&Updater(None, Some("app-windows.zip".into())), &release);
+        assert_eq!(named.unwrap().name, "app-windows.zip");
+        let err = select_asset(&Updater(None, Some("app.msi".into())), &release).unwrap_err();
+        assert!(matches!(err, Error::Release(_)), "{}", err);
+        assert!(err.to_string().contains("app.msi"), "{}", err);
+        assert!(err.to_string().contains("app.tar.gz"), "{}", err);
+    }
+
+    #[test]
+    fn detect_cwd_install_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = dir.path().join("app");
+        let other = dir.path().join("other");
+        std::fs::create_dir(&app).unwrap();
+        std::fs::create_dir(&other).unwrap();
+
+        let paths = vec![other.clone(), app.join(".")];
+        assert_eq!(cwd_install_dir(&paths, &app), Some(app.join(".")));
+        assert_eq!(cwd_install_dir(&paths, dir.path()), None);
+        assert_eq!(cwd_install_dir(&[], &app), None);
+    }
+
+    #[test]
+    fn swap_all_rolls_back() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("self_update_swap_all")
+            .tempdir()
+            .expect("tempdir fail");
+        let dir = tmp_dir.path();
+        let read = |p: &Path| fs::read_to_string(p).expect("read fail");
+
+        let active = dir.join("active");
+        let standby = dir.join("standby");
+        fs::write(&active, "old").unwrap();
+        fs::write(&standby, "old").unwrap();
+        fs::write(sibling_path(&active, "update-new"), "new").unwrap();
+        fs::write(sibling_path(&standby, "update-new"), "new").unwrap();
+        let swaps = vec![
+            (sibling_path(&active, "update-new"), active.clone()),
+            (sibling_path(&standby, "update-new"), standby.clone()),
+        ];
+        swap_all(&swaps).expect("swap fail");
+        assert_eq!("new", read(&active));
+        assert_eq!("new", read(&standby));
+        assert!(!sibling_path(&active, "update-old").exists());
+
+        // the second staged path is missing, the first swap is rolled back
+        fs::write(sibling_path(&active, "update-new"), "newer").unwrap();
+        assert!(swap_all(&swaps).is_err());
+        assert_eq!("new", read(&active));
+        assert_eq!("new", read(&standby));
+    }
+
+    #[test]
+    fn atomic_replace() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        let bin = dir.join("app");
+        let new_exe = dir.join("new_app");
+        fs::write(&bin, "old").unwrap();
+        fs::write(&new_exe, "new binary").unwrap();
+
+        // a crash mid-write leaves a partial staged file, the installed binary is untouched
+        let staged = sibling_path(&bin, "update-new");
+        fs::write(&staged, "new bi").unwrap();
+        assert_eq!(fs::read_to_string(&bin).unwrap(), "old");
+
+        // the next attempt overwrites the leftover and renames it over the binary, which
+        // stays readable through handles opened before the swap
+        let mut running = File::open(&bin).unwrap();
+        stage_file(&new_exe, &staged, &bin).unwrap();
+        swap_all(&[(staged.clone(), bin.clone())]).unwrap();
+        assert_eq!(fs::read_to_string(&bin).unwrap(), "new binary");
+        assert!(!staged.exists());
+        assert!(!sibling_path(&bin, "update-old").exists());
+        let mut old = String::new();
+        std::io::Read::read_to_string(&mut running, &mut old).unwrap();
+        assert_eq!(old, "old");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stage_file_keeps_mode() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        let new_exe = dir.join("new_app");
+        fs::write(&new_exe, "new").unwrap();
+        fs::set_permissions(&new_exe, std::fs::Permissions::from_mode(0o600)).unwrap();
+        let mode = |p: &Path| fs::metadata(p).unwrap().permissions().mode() & 0o7777;
+
+        let bin = dir.join("app");
+        let staged = dir.join("app.update-new");
+        stage_file(&new_exe, &staged, &bin).unwrap();
+        assert_eq!(mode(&staged), 0o755);
+
+        fs::write(&bin, "old").unwrap();
+        fs::set_permissions(&bin, std::fs::Permissions::from_mode(0o770)).unwrap();
+        stage_file(&new_exe, &staged, &bin).unwrap();
+        assert_eq!(mode(&staged), 0o775);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn move_running_exe_aside() {
+        let exe = env::current_exe().unwrap();
+        assert_eq!(
+            move_self_aside(&[PathBuf::from("C:\\missing.exe")]).unwrap(),
+            None
+        );
+
+        let (moved, old) = move_self_aside(&[exe.clone()]).unwrap().unwrap();
+        assert_eq!(moved, exe);
+        assert!(!exe.exists() && old.exists());
+        // put a copy of the running test binary back in place
+        fs::copy(&old, &exe).unwrap();
+        delete_on_reboot(&old);
+    }
+
+    #[test]
+    fn backup_restore() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        let install_dir = dir.join("app");
+        fs::create_dir_all(install_dir.join("lib")).unwrap();
+        fs::write(install_dir.join("app"), "old").unwrap();
+        fs::write(install_dir.join("lib").join("dep"), "old dep").unwrap();
+        let bin = dir.join("tool");
+        fs::write(&bin, "old tool").unwrap();
+
+        let backups = vec![
+            (install_dir.clone(), sibling_path(&install_dir, "bak")),
+            (bin.clone(), dir.join("tool.backup")),
+        ];
+        for (path, backup) in &backups {
+            copy_path(path, backup).unwrap();
+        }
+        fs::remove_dir_all(install_dir.join("lib")).unwrap();
+        fs::write(install_dir.join("app"), "broken").unwrap();
+        fs::write(&bin, "broken").unwrap();
+
+        restore_backups(&backups);
+        assert_eq!(fs::read_to_string(install_dir.join("app")).unwrap(), "old");
+        assert_eq!(
+            fs::read_to_string(install_dir.join("lib").join("dep")).unwrap(),
+            "old dep"
+        );
+        assert_eq!(fs::read_to_string(&bin).unwrap(), "old tool");
+        assert!(!dir.join("app.bak").exists());
+        assert!(!dir.join("tool.backup").exists());
+    }
+
+    #[test]
+    fn compare_versions() {
+        assert!(newer_version("1.2.0", "1.10.0", false));
+        assert!(!newer_version("1.10.0", "1.2.0", false));
+        assert!(!newer_version("1.2.0", "1.2.0", false));
+        assert!(!newer_version("1.2.0", "1.2.0+build.2", false));
+        assert!(newer_version("1.2.0+build.1", "1.2.0+build.2", true));
+        assert!(newer_version("2021.3", "2021.4", false));
+        assert!(!newer_version("2021.4", "2021.4", false));
+    }
+
+    #[test]
+    fn latest_semver_release() {
+        let release = |version: &str| Release {
+            version: version.into(),
+            ..Default::default()
+        };
+        let releases = vec![
+            release("1.2.0"),
+            release("v1.10.0"),
+            release("nightly"),
+            release("1.9.3"),
+            release("1.10.0-rc.1"),
+        ];
+        assert_eq!(latest_release(releases).unwrap().version, "v1.10.0");
+        assert!(latest_release(vec![release("nightly")]).is_none());
+    }
+
+    #[test]
+    fn sort_semver_releases() {
+        let release = |version: &str| Release {
+            version: version.into(),
+            ..Default::default()
+        };
+        let releases = vec![
+            release("nightly"),
+            release("1.2.0"),
+            release("v1.10.0"),
+            release("beta"),
+            release("1.10.0-rc.1"),
+        ];
+        let versions = sort_releases(releases)
+            .into_iter()
+            .map(|r| r.version)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            versions,
+            vec!["v1.10.0", "1.10.0-rc.1", "1.2.0", "nightly", "beta"]
+        );
+    }
+
+    #[cfg(feature = "archive-zip")]
+    #[test]
+    fn stage_dir_keeps_existing_files() {
+        use std::io::Write;
+
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("self_update_stage_dir")
+            .tempdir()
+            .expect("tempdir fail");
+        let dir = tmp_dir.path();
+        let install_dir = dir.join("install");
+        fs::create_dir(&install_dir).unwrap();
+        fs::write(install_dir.join("app"), "old").unwrap();
+        fs::write(install_dir.join("config"), "keep").unwrap();
+
+        let archive = dir.join("app.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&archive).unwrap());
+        zip.start_file("app", zip::write::FileOptions::default())
+            .unwrap();
+        zip.write_all(b"new").unwrap();
+        zip.finish().unwrap();
+
+        let staged = sibling_path(&install_dir, "update-new");
+        stage_dir(&archive, &install_dir, &staged).expect("stage fail");
+        // nothing changes until the staged dir is swapped in
+        assert_eq!("old", fs::read_to_string(install_dir.join("app")).unwrap());
+        swap_all(&[(staged.clone(), install_dir.clone())]).expect("swap fail");
+        assert_eq!("new", fs::read_to_string(install_dir.join("app")).unwrap());
+        assert_eq!(
+            "keep",
+            fs::read_to_string(install_dir.join("config")).unwrap()
+        );
+        assert!(!staged.exists());
+        assert!(!sibling_path(&install_dir, "update-old").exists());
+    }
 }
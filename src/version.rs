@@ -1,15 +1,40 @@
 /*! Semver version checks
 
-The following functions compare two semver compatible version strings.
+The following functions compare two semver compatible version strings,
+or probe the version reported by an installed binary.
 */
 use crate::errors::*;
+use regex::Regex;
 use semver::Version;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+
+/// Default normalization of version strings before comparison: surrounding whitespace
+/// and a leading `v` or `V` are removed, e.g. ` v9.9.10 ` becomes `9.9.10`
+pub fn normalize(ver: &str) -> String {
+    let ver = crate::trim_text(ver);
+    ver.strip_prefix(|c| c == 'v' || c == 'V')
+        .unwrap_or(ver)
+        .to_owned()
+}
 
 /// Check if a version is greater than the current
 pub fn bump_is_greater(current: &str, other: &str) -> Result<bool> {
     Ok(Version::parse(other)? > Version::parse(current)?)
 }
 
+/// Check if a version is greater than the current, falling back to comparing
+/// build metadata (e.g. `1.2.0+build.42`) when both versions are otherwise equal.
+/// Build metadata identifiers are compared in order, numerically where both are numeric.
+pub fn bump_is_greater_build(current: &str, other: &str) -> Result<bool> {
+    let current = Version::parse(current)?;
+    let other = Version::parse(other)?;
+    Ok(other > current || (other == current && other.build > current.build))
+}
+
 /// Check if a new version is compatible with the current
 pub fn bump_is_compatible(current: &str, other: &str) -> Result<bool> {
     let current = Version::parse(current)?;
@@ -46,6 +71,110 @@ pub fn bump_is_patch(current: &str, other: &str) -> Result<bool> {
     Ok(current.major == other.major && current.minor == other.minor && other.patch > current.patch)
 }
 
+// Regex of `extract_version`, the first version-looking token
+fn default_version_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\d+\S+").expect("invalid version regex"))
+}
+
+/// Extract the first version-looking token (e.g. `1.2.3` in `app 1.2.3 (abc123)`)
+/// from the output of a `--version` invocation
+pub fn extract_version(output: &str) -> Option<String> {
+    extract_version_with(output, default_version_regex())
+}
+
+/// Extract the version matching `re` from `output`: the first capture group when the
+/// regex has one, e.g. `version: (\S+)`, the whole match otherwise
+pub fn extract_version_with(output: &str, re: &Regex) -> Option<String> {
+    let caps = re.captures(crate::trim_text(output))?;
+    caps.get(1)
+        .or_else(|| caps.get(0))
+        .map(|m| m.as_str().to_owned())
+}
+
+/// Run `bin arg` (e.g. `--version` or `-V`) and extract the version matching `re` from
+/// its output, reading stderr when the command fails or prints nothing to stdout.
+/// Returns `None` if the binary can't be run or reports no version.
+pub fn probe_version<P: AsRef<Path>>(bin: P, arg: &str, re: &Regex) -> Option<String> {
+    let msg = version_output(bin.as_ref(), &[arg]).ok()?;
+    extract_version_with(&msg, re)
+}
+
+// Output of `bin args`, stdout followed by stderr
+fn version_output(bin: &Path, args: &[&str]) -> Result<String> {
+    Ok(output_text(&Command::new(bin).args(args).output()?))
+}
+
+// Text a version is extracted from, the command's stdout followed by its stderr
+pub(crate) fn output_text(output: &std::process::Output) -> String {
+    format!(
+        "{}\n{}",
+        String::from_utf8_lossy(&output.stdout).trim(),
+        String::from_utf8_lossy(&output.stderr).trim()
+    )
+}
+
+/// Run `bin --version` and extract the reported version from its output,
+/// see `bin_version_with_args`
+pub fn bin_version<P: AsRef<Path>>(bin: P) -> Result<String> {
+    bin_version_with_args(bin, &["--version"])
+}
+
+/// Run `bin args` (e.g. `-V` or `version`) and extract the reported version from its
+/// stdout, or its stderr when stdout reports none. Fails with `Error::Config` if no
+/// version is found.
+pub fn bin_version_with_args<P: AsRef<Path>>(bin: P, args: &[&str]) -> Result<String> {
+    let bin = bin.as_ref();
+    let msg = version_output(bin, args)?;
+    extract_version(&msg).ok_or_else(|| {
+        format_err!(
+            Error::Config,
+            "No version found in `{:?} {}` output: {:?}",
+            bin,
+            args.join(" "),
+            msg.trim()
+        )
+    })
+}
+
+// Versions probed by `cached_bin_version`, keyed on the binary's path, along with
+// the modification time and size of the probed file
+type ProbeCache = HashMap<PathBuf, (SystemTime, u64, String)>;
+
+fn probe_cache() -> &'static Mutex<ProbeCache> {
+    static CACHE: OnceLock<Mutex<ProbeCache>> = OnceLock::new();
+    CACHE.get_or_init(Default::default)
+}
+
+/// Same as `bin_version`, caching the result per binary path. The binary is probed again
+/// once its modification time or size changes, e.g. after an update replaced it.
+pub fn cached_bin_version<P: AsRef<Path>>(bin: P) -> Result<String> {
+    cached_bin_version_with_args(bin, &["--version"])
+}
+
+/// Same as `bin_version_with_args`, caching the result like `cached_bin_version`
+pub fn cached_bin_version_with_args<P: AsRef<Path>>(bin: P, args: &[&str]) -> Result<String> {
+    let bin = bin.as_ref();
+    let metadata = std::fs::metadata(bin)?;
+    let (mtime, len) = (metadata.modified()?, metadata.len());
+    if let Some((cached_mtime, cached_len, ver)) = probe_cache().lock().unwrap().get(bin) {
+        if *cached_mtime == mtime && *cached_len == len {
+            return Ok(ver.clone());
+        }
+    }
+    let ver = bin_version_with_args(bin, args)?;
+    probe_cache()
+        .lock()
+        .unwrap()
+        .insert(bin.to_owned(), (mtime, len, ver.clone()));
+    Ok(ver)
+}
+
+/// Forget the version cached by `cached_bin_version` for `bin`, forcing it to be probed again
+pub fn forget_bin_version<P: AsRef<Path>>(bin: P) {
+    probe_cache().lock().unwrap().remove(bin.as_ref());
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -57,45 +186,124 @@ mod test {
         assert!(bump_is_greater("0.2.0", "0.2.3").unwrap());
     }
 
+    #[test]
+    fn test_bump_greater_build() {
+        assert!(bump_is_greater_build("1.2.0", "1.2.3").unwrap());
+        assert!(bump_is_greater_build("1.2.0", "1.2.0+build.1").unwrap());
+        assert!(bump_is_greater_build("1.2.0+build.9", "1.2.0+build.42").unwrap());
+        assert!(bump_is_greater_build("1.2.0+build.42", "1.2.1+build.1").unwrap());
+        assert!(!bump_is_greater_build("1.2.0+build.42", "1.2.0+build.42").unwrap());
+        assert!(!bump_is_greater_build("1.2.0+build.42", "1.2.0+build.9").unwrap());
+        assert!(!bump_is_greater_build("1.2.1+build.1", "1.2.0+build.42").unwrap());
+        assert!(!bump_is_greater("1.2.0+build.9", "1.2.0+build.42").unwrap());
+    }
+
     #[test]
     fn test_bump_is_compatible() {
-        assert_eq!(false, bump_is_compatible("1.2.0", "2.3.1").unwrap());
-        assert_eq!(false, bump_is_compatible("0.2.0", "2.3.1").unwrap());
-        assert_eq!(false, bump_is_compatible("1.2.3", "3.3.0").unwrap());
-        assert_eq!(false, bump_is_compatible("1.2.3", "0.2.0").unwrap());
-        assert_eq!(false, bump_is_compatible("0.2.0", "0.3.0").unwrap());
-        assert_eq!(false, bump_is_compatible("0.3.0", "0.2.0").unwrap());
-        assert_eq!(false, bump_is_compatible("1.2.3", "1.1.0").unwrap());
-        assert_eq!(true, bump_is_compatible("1.2.0", "1.2.3").unwrap());
-        assert_eq!(true, bump_is_compatible("0.2.0", "0.2.3").unwrap());
-        assert_eq!(true, bump_is_compatible("1.2.0", "1.3.3").unwrap());
+        assert!(!bump_is_compatible("1.2.0", "2.3.1").unwrap());
+        assert!(!bump_is_compatible("0.2.0", "2.3.1").unwrap());
+        assert!(!bump_is_compatible("1.2.3", "3.3.0").unwrap());
+        assert!(!bump_is_compatible("1.2.3", "0.2.0").unwrap());
+        assert!(!bump_is_compatible("0.2.0", "0.3.0").unwrap());
+        assert!(!bump_is_compatible("0.3.0", "0.2.0").unwrap());
+        assert!(!bump_is_compatible("1.2.3", "1.1.0").unwrap());
+        assert!(bump_is_compatible("1.2.0", "1.2.3").unwrap());
+        assert!(bump_is_compatible("0.2.0", "0.2.3").unwrap());
+        assert!(bump_is_compatible("1.2.0", "1.3.3").unwrap());
     }
 
     #[test]
     fn test_bump_is_major() {
-        assert_eq!(true, bump_is_major("1.2.0", "2.3.1").unwrap());
-        assert_eq!(true, bump_is_major("0.2.0", "2.3.1").unwrap());
-        assert_eq!(true, bump_is_major("1.2.3", "3.3.0").unwrap());
-        assert_eq!(false, bump_is_major("1.2.3", "1.2.0").unwrap());
-        assert_eq!(false, bump_is_major("1.2.3", "0.2.0").unwrap());
+        assert!(bump_is_major("1.2.0", "2.3.1").unwrap());
+        assert!(bump_is_major("0.2.0", "2.3.1").unwrap());
+        assert!(bump_is_major("1.2.3", "3.3.0").unwrap());
+        assert!(!bump_is_major("1.2.3", "1.2.0").unwrap());
+        assert!(!bump_is_major("1.2.3", "0.2.0").unwrap());
     }
 
     #[test]
     fn test_bump_is_minor() {
-        assert_eq!(false, bump_is_minor("1.2.0", "2.3.1").unwrap());
-        assert_eq!(false, bump_is_minor("0.2.0", "2.3.1").unwrap());
-        assert_eq!(false, bump_is_minor("1.2.3", "3.3.0").unwrap());
-        assert_eq!(true, bump_is_minor("1.2.3", "1.3.0").unwrap());
-        assert_eq!(true, bump_is_minor("0.2.3", "0.4.0").unwrap());
+        assert!(!bump_is_minor("1.2.0", "2.3.1").unwrap());
+        assert!(!bump_is_minor("0.2.0", "2.3.1").unwrap());
+        assert!(!bump_is_minor("1.2.3", "3.3.0").unwrap());
+        assert!(bump_is_minor("1.2.3", "1.3.0").unwrap());
+        assert!(bump_is_minor("0.2.3", "0.4.0").unwrap());
     }
 
     #[test]
     fn test_bump_is_patch() {
-        assert_eq!(false, bump_is_patch("1.2.0", "2.3.1").unwrap());
-        assert_eq!(false, bump_is_patch("0.2.0", "2.3.1").unwrap());
-        assert_eq!(false, bump_is_patch("1.2.3", "3.3.0").unwrap());
-        assert_eq!(false, bump_is_patch("1.2.3", "1.2.3").unwrap());
-        assert_eq!(true, bump_is_patch("1.2.0", "1.2.3").unwrap());
-        assert_eq!(true, bump_is_patch("0.2.3", "0.2.4").unwrap());
+        assert!(!bump_is_patch("1.2.0", "2.3.1").unwrap());
+        assert!(!bump_is_patch("0.2.0", "2.3.1").unwrap());
+        assert!(!bump_is_patch("1.2.3", "3.3.0").unwrap());
+        assert!(!bump_is_patch("1.2.3", "1.2.3").unwrap());
+        assert!(bump_is_patch("1.2.0", "1.2.3").unwrap());
+        assert!(bump_is_patch("0.2.3", "0.2.4").unwrap());
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("v9.9.10"), "9.9.10");
+        assert_eq!(normalize(" V9.9.10\r\n"), "9.9.10");
+        assert_eq!(normalize("9.9.10"), "9.9.10");
+        assert_eq!(normalize("vv1"), "v1");
+    }
+
+    #[test]
+    fn test_cached_bin_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let bin = dir.path().join("app");
+        std::fs::write(&bin, "not executable").unwrap();
+        assert!(cached_bin_version(&bin).is_err());
+
+        let metadata = std::fs::metadata(&bin).unwrap();
+        probe_cache().lock().unwrap().insert(
+            bin.clone(),
+            (metadata.modified().unwrap(), metadata.len(), "1.2.3".into()),
+        );
+        assert_eq!(cached_bin_version(&bin).unwrap(), "1.2.3");
+
+        forget_bin_version(&bin);
+        assert!(cached_bin_version(&bin).is_err());
+    }
+
+    #[test]
+    fn test_extract_version() {
+        assert_eq!(extract_version("app 1.2.3\n"), Some("1.2.3".into()));
+        assert_eq!(
+            extract_version("CloudAgent v2.0.1-beta (x64)"),
+            Some("2.0.1-beta".into())
+        );
+        assert_eq!(extract_version("unknown"), None);
+
+        let re = Regex::new(r"version: (\S+)").unwrap();
+        assert_eq!(
+            extract_version_with("tool 7\nversion: 1.4.0-rc1\n", &re),
+            Some("1.4.0-rc1".into())
+        );
+        assert_eq!(extract_version_with("tool 7", &re), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_probe_version() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let bin = dir.path().join("tool");
+        std::fs::write(
+            &bin,
+            "#!/bin/sh\n[ \"$1\" = -V ] && echo \"tool 2.3.4\" && exit 0\necho \"bad flag $1\" >&2\nexit 1\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&bin, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let re = default_version_regex();
+        assert_eq!(probe_version(&bin, "-V", re), Some("2.3.4".into()));
+        assert_eq!(probe_version(&bin, "--version", re), None);
+        assert_eq!(probe_version(dir.path().join("missing"), "-V", re), None);
+
+        assert_eq!(bin_version_with_args(&bin, &["-V"]).unwrap(), "2.3.4");
+        let err = bin_version_with_args(&bin, &["version", "--short"]).unwrap_err();
+        assert!(matches!(err, Error::Config(_)), "{}", err);
     }
 }